@@ -0,0 +1,802 @@
+use crate::api_structs::*;
+use crate::recorder::Recorder;
+use crate::{rate_limit, strict, warning, GplayError, GplayLog};
+use gcp_auth::Token;
+use reqwest::{
+    header::{LOCATION, RANGE, RETRY_AFTER},
+    Body, Client, RequestBuilder, Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// An error response from the API, with the reasons it gave for the failure
+#[derive(Debug)]
+pub struct ResponseError {
+    pub status: StatusCode,
+    pub error: Option<ApiError>,
+}
+
+impl ResponseError {
+    pub fn has_reason(&self, reason: &str) -> bool {
+        self.error.as_ref().map_or(false, |error| {
+            error
+                .errors
+                .iter()
+                .any(|detail| detail.reason.as_deref() == Some(reason))
+        })
+    }
+
+    /// Whether an API response with the given reason caused this error, e.g. `editExpired`
+    pub fn caused(error: &(dyn Error + 'static), reason: &str) -> bool {
+        let mut source = Some(error);
+
+        while let Some(error) = source {
+            if let Some(response_error) = error.downcast_ref::<ResponseError>() {
+                if response_error.has_reason(reason) {
+                    return true;
+                }
+            }
+
+            source = error.source();
+        }
+
+        false
+    }
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.error {
+            Some(error) => f.write_str(&error.message),
+            None => write!(f, "{}", self.status),
+        }
+    }
+}
+
+impl Error for ResponseError {}
+
+/// Something that can be uploaded to an edit, which decides where it is sent
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArtifactKind {
+    Bundle {
+        ack_install_warning: bool,
+    },
+    Apk,
+    /// A file for deobfuscating crash reports of a version, either a `proguard` mapping
+    /// file or `nativeCode` debug symbols
+    DeobfuscationFile {
+        version_code: i32,
+        file_type: String,
+    },
+}
+
+impl ArtifactKind {
+    /// The path of the upload endpoint within an edit
+    pub fn path(&self) -> String {
+        match self {
+            ArtifactKind::Bundle { .. } => "bundles".to_string(),
+            ArtifactKind::Apk => "apks".to_string(),
+            ArtifactKind::DeobfuscationFile {
+                version_code,
+                file_type,
+            } => format!("apks/{}/deobfuscationFiles/{}", version_code, file_type),
+        }
+    }
+
+    /// Query parameters for the upload, besides the upload type
+    pub fn query(&self) -> Vec<(&'static str, String)> {
+        match self {
+            ArtifactKind::Bundle {
+                ack_install_warning,
+            } => vec![(
+                "ackBundleInstallationWarning",
+                ack_install_warning.to_string(),
+            )],
+            _ => vec![],
+        }
+    }
+
+    /// The content type that the artifact is uploaded with unless another is given
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArtifactKind::Apk => "application/vnd.android.package-archive",
+            _ => "application/octet-stream",
+        }
+    }
+}
+
+impl fmt::Display for ArtifactKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArtifactKind::Bundle { .. } => f.write_str("bundle"),
+            ArtifactKind::Apk => f.write_str("APK"),
+            ArtifactKind::DeobfuscationFile { file_type, .. } if file_type == "nativeCode" => {
+                f.write_str("native debug symbols")
+            }
+            ArtifactKind::DeobfuscationFile { .. } => f.write_str("mapping file"),
+        }
+    }
+}
+
+/// How far a resumable upload has got
+#[derive(Debug)]
+pub enum UploadProgress<T> {
+    /// The number of bytes from the start of the artifact that the server has received
+    Received(u64),
+    Complete(T),
+}
+
+// The number of bytes received from the `Range` header of a resumable upload response, e.g.
+// `bytes=0-1048575`, where no header means that nothing has been received
+fn parse_received(range: Option<&str>) -> Result<u64, GplayError> {
+    match range {
+        Some(range) => range
+            .strip_prefix("bytes=0-")
+            .and_then(|last_byte| last_byte.parse::<u64>().ok())
+            .map(|last_byte| last_byte + 1)
+            .ok_or_else(|| GplayError::Parse(format!("Unexpected upload range '{}'", range))),
+        None => Ok(0),
+    }
+}
+
+/// A thin client for the Android Publisher REST API, with one method per endpoint that gplay
+/// uses. Requests are retried when rate limited, and error responses are turned into
+/// [`GplayError::Api`] errors carrying the API's message.
+pub struct AndroidPublisherApi<'a> {
+    client: &'a Client,
+    token: &'a Token,
+    log: &'a dyn GplayLog,
+    strict: bool,
+    recorder: Option<&'a Recorder>,
+}
+
+impl<'a> AndroidPublisherApi<'a> {
+    const EDIT_URL: &str =
+        "https://androidpublisher.googleapis.com/androidpublisher/v3/applications";
+    const UPLOAD_URL: &str =
+        "https://androidpublisher.googleapis.com/upload/androidpublisher/v3/applications";
+    #[cfg(feature = "games")]
+    const GAMES_CONFIGURATION_URL: &str =
+        "https://gamesconfiguration.googleapis.com/games/v1configuration/applications";
+    const MAX_RATE_LIMIT_RETRIES: usize = 3;
+    const MAX_REPLY_LENGTH: usize = 350;
+
+    /// The token must have the `https://www.googleapis.com/auth/androidpublisher` scope
+    pub fn new(
+        client: &'a Client,
+        token: &'a Token,
+        log: &'a dyn GplayLog,
+    ) -> AndroidPublisherApi<'a> {
+        AndroidPublisherApi {
+            client,
+            token,
+            log,
+            strict: false,
+            recorder: None,
+        }
+    }
+
+    /// Warn about response fields that the models in `api_structs` do not have
+    pub fn strict(mut self, strict: bool) -> AndroidPublisherApi<'a> {
+        self.strict = strict;
+        self
+    }
+
+    /// Record every request and response, e.g. for a bug report
+    pub(crate) fn recorder(mut self, recorder: Option<&'a Recorder>) -> AndroidPublisherApi<'a> {
+        self.recorder = recorder;
+        self
+    }
+
+    /// `edits.insert`
+    pub async fn insert_edit(&self, package_name: &str) -> Result<EditInsert, GplayError> {
+        self.get_response::<EditInsert>(
+            self.send(
+                self.client
+                    .post(format!(
+                        "{}/{package_name}/edits",
+                        Self::EDIT_URL,
+                        package_name = package_name
+                    ))
+                    .bearer_auth(self.token.as_str())
+                    .body("{}"),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    /// `edits.validate`
+    pub async fn validate_edit(&self, package_name: &str, edit_id: &str) -> Result<(), GplayError> {
+        self.get_response::<EditInsert>(
+            self.send(
+                self.client
+                    .post(format!(
+                        "{}/{package_name}/edits/{edit_id}:validate",
+                        Self::EDIT_URL,
+                        package_name = package_name,
+                        edit_id = edit_id
+                    ))
+                    .bearer_auth(self.token.as_str())
+                    .header("Content-Length", 0),
+            )
+            .await?,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    /// `edits.commit`
+    pub async fn commit_edit(&self, package_name: &str, edit_id: &str) -> Result<(), GplayError> {
+        Self::get_empty_response(
+            self.send(
+                self.client
+                    .post(format!(
+                        "{}/{package_name}/edits/{edit_id}:commit",
+                        Self::EDIT_URL,
+                        package_name = package_name,
+                        edit_id = edit_id
+                    ))
+                    .bearer_auth(self.token.as_str())
+                    .header("Content-Length", 0),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    /// `edits.delete`
+    pub async fn delete_edit(&self, package_name: &str, edit_id: &str) -> Result<(), GplayError> {
+        Self::get_empty_response(
+            self.send(
+                self.client
+                    .delete(format!(
+                        "{}/{package_name}/edits/{edit_id}",
+                        Self::EDIT_URL,
+                        package_name = package_name,
+                        edit_id = edit_id
+                    ))
+                    .bearer_auth(self.token.as_str()),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    /// `edits.bundles.list`
+    pub async fn list_bundles(
+        &self,
+        package_name: &str,
+        edit_id: &str,
+    ) -> Result<EditBundlesList, GplayError> {
+        self.get_response::<EditBundlesList>(
+            self.send(
+                self.client
+                    .get(format!(
+                        "{}/{package_name}/edits/{edit_id}/bundles",
+                        Self::EDIT_URL,
+                        package_name = package_name,
+                        edit_id = edit_id
+                    ))
+                    .bearer_auth(self.token.as_str()),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    /// `edits.apks.list`
+    pub async fn list_apks(
+        &self,
+        package_name: &str,
+        edit_id: &str,
+    ) -> Result<ApksList, GplayError> {
+        self.get_response::<ApksList>(
+            self.send(
+                self.client
+                    .get(format!(
+                        "{}/{package_name}/edits/{edit_id}/apks",
+                        Self::EDIT_URL,
+                        package_name = package_name,
+                        edit_id = edit_id
+                    ))
+                    .bearer_auth(self.token.as_str()),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    // The upload URL of an artifact in an edit
+    fn upload_url(package_name: &str, edit_id: &str, kind: &ArtifactKind) -> String {
+        format!(
+            "{}/{package_name}/edits/{edit_id}/{path}",
+            Self::UPLOAD_URL,
+            package_name = package_name,
+            edit_id = edit_id,
+            path = kind.path()
+        )
+    }
+
+    /// `edits.bundles.upload`, `edits.apks.upload` or `edits.deobfuscationfiles.upload`, as a
+    /// simple media upload of `num_bytes` from `body`. Streaming bodies cannot be resent, so
+    /// the upload is not retried if rate limited.
+    pub async fn upload_artifact<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        package_name: &str,
+        edit_id: &str,
+        kind: &ArtifactKind,
+        content_type: &str,
+        body: impl Into<Body>,
+        num_bytes: u64,
+        timeout: Duration,
+    ) -> Result<T, GplayError> {
+        self.get_response::<T>(
+            self.execute(
+                self.client
+                    .post(Self::upload_url(package_name, edit_id, kind))
+                    .query(&[("uploadType", "media")])
+                    .query(&kind.query())
+                    .timeout(timeout)
+                    .bearer_auth(self.token.as_str())
+                    .header("Content-Type", content_type)
+                    .header("Content-Length", num_bytes)
+                    .body(body),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    /// Start a resumable upload of an artifact, returning the URL of the upload session
+    pub async fn start_artifact_upload(
+        &self,
+        package_name: &str,
+        edit_id: &str,
+        kind: &ArtifactKind,
+        content_type: &str,
+        num_bytes: u64,
+    ) -> Result<String, GplayError> {
+        let response = self
+            .execute(
+                self.client
+                    .post(Self::upload_url(package_name, edit_id, kind))
+                    .query(&[("uploadType", "resumable")])
+                    .query(&kind.query())
+                    .bearer_auth(self.token.as_str())
+                    .header("X-Upload-Content-Type", content_type)
+                    .header("X-Upload-Content-Length", num_bytes)
+                    .header("Content-Length", 0),
+            )
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(Self::response_error(response).await);
+        }
+
+        response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .map(|location| location.to_string())
+            .ok_or_else(|| {
+                GplayError::Other(format!("No upload session was returned for the {}", kind))
+            })
+    }
+
+    /// Send the part of a resumable upload that starts at `offset`
+    pub async fn upload_chunk<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        session_url: &str,
+        body: impl Into<Body>,
+        offset: u64,
+        chunk_len: u64,
+        num_bytes: u64,
+        timeout: Duration,
+    ) -> Result<UploadProgress<T>, GplayError> {
+        let response = self
+            .execute(
+                self.client
+                    .put(session_url)
+                    .timeout(timeout)
+                    .bearer_auth(self.token.as_str())
+                    .header("Content-Length", chunk_len)
+                    .header(
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", offset, offset + chunk_len - 1, num_bytes),
+                    )
+                    .body(body),
+            )
+            .await?;
+
+        self.get_upload_progress(response).await
+    }
+
+    /// Ask how much of a resumable upload has been received, e.g. after a chunk failed
+    pub async fn query_upload<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        session_url: &str,
+        num_bytes: u64,
+    ) -> Result<UploadProgress<T>, GplayError> {
+        let response = self
+            .execute(
+                self.client
+                    .put(session_url)
+                    .bearer_auth(self.token.as_str())
+                    .header("Content-Length", 0)
+                    .header("Content-Range", format!("bytes */{}", num_bytes)),
+            )
+            .await?;
+
+        self.get_upload_progress(response).await
+    }
+
+    /// `edits.tracks.list`
+    pub async fn list_tracks(
+        &self,
+        package_name: &str,
+        edit_id: &str,
+    ) -> Result<TracksList, GplayError> {
+        self.get_response::<TracksList>(
+            self.send(
+                self.client
+                    .get(format!(
+                        "{}/{package_name}/edits/{edit_id}/tracks",
+                        Self::EDIT_URL,
+                        package_name = package_name,
+                        edit_id = edit_id
+                    ))
+                    .bearer_auth(self.token.as_str()),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    /// `edits.tracks.get`
+    pub async fn get_track(
+        &self,
+        package_name: &str,
+        edit_id: &str,
+        track_name: &str,
+    ) -> Result<Track, GplayError> {
+        self.get_response::<Track>(
+            self.send(
+                self.client
+                    .get(format!(
+                        "{}/{package_name}/edits/{edit_id}/tracks/{track_name}",
+                        Self::EDIT_URL,
+                        package_name = package_name,
+                        edit_id = edit_id,
+                        track_name = track_name
+                    ))
+                    .bearer_auth(self.token.as_str()),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    /// `edits.tracks.update`
+    pub async fn update_track(
+        &self,
+        package_name: &str,
+        edit_id: &str,
+        track: &Track,
+    ) -> Result<Track, GplayError> {
+        self.get_response::<Track>(
+            self.send(
+                self.client
+                    .put(format!(
+                        "{}/{package_name}/edits/{edit_id}/tracks/{track_name}",
+                        Self::EDIT_URL,
+                        package_name = package_name,
+                        edit_id = edit_id,
+                        track_name = track.name
+                    ))
+                    .bearer_auth(self.token.as_str())
+                    .json(track),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    /// `reviews.list`, one page at a time starting with no `page_token`
+    pub async fn list_reviews(
+        &self,
+        package_name: &str,
+        page_token: Option<&str>,
+    ) -> Result<ReviewsList, GplayError> {
+        let mut request = self
+            .client
+            .get(format!(
+                "{}/{package_name}/reviews",
+                Self::EDIT_URL,
+                package_name = package_name
+            ))
+            .bearer_auth(self.token.as_str())
+            .query(&[("maxResults", "100")]);
+
+        if let Some(page_token) = page_token {
+            request = request.query(&[("token", page_token)]);
+        }
+
+        self.get_response::<ReviewsList>(self.send(request).await?)
+            .await
+    }
+
+    /// `reviews.reply`
+    pub async fn reply_review(
+        &self,
+        package_name: &str,
+        review_id: &str,
+        text: &str,
+    ) -> Result<ReviewsReplyResponse, GplayError> {
+        if text.chars().count() > Self::MAX_REPLY_LENGTH {
+            return Err(GplayError::Other(format!(
+                "Reply is longer than {} characters",
+                Self::MAX_REPLY_LENGTH
+            )));
+        }
+
+        self.get_response::<ReviewsReplyResponse>(
+            self.send(
+                self.client
+                    .post(format!(
+                        "{}/{package_name}/reviews/{review_id}:reply",
+                        Self::EDIT_URL,
+                        package_name = package_name,
+                        review_id = review_id
+                    ))
+                    .bearer_auth(self.token.as_str())
+                    .json(&ReviewsReplyRequest {
+                        reply_text: text.to_string(),
+                    }),
+            )
+            .await?,
+        )
+        .await
+    }
+
+    /// `achievementConfigurations.list` of the Play Games Services configuration API, one page
+    /// at a time starting with no `page_token`
+    #[cfg(feature = "games")]
+    pub async fn list_achievements(
+        &self,
+        application_id: &str,
+        page_token: Option<&str>,
+    ) -> Result<AchievementConfigurationList, GplayError> {
+        let mut request = self
+            .client
+            .get(format!(
+                "{}/{application_id}/achievements",
+                Self::GAMES_CONFIGURATION_URL,
+                application_id = application_id
+            ))
+            .bearer_auth(self.token.as_str());
+
+        if let Some(page_token) = page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+
+        self.get_response::<AchievementConfigurationList>(self.send(request).await?)
+            .await
+    }
+
+    /// `leaderboardConfigurations.list` of the Play Games Services configuration API, one page
+    /// at a time starting with no `page_token`
+    #[cfg(feature = "games")]
+    pub async fn list_leaderboards(
+        &self,
+        application_id: &str,
+        page_token: Option<&str>,
+    ) -> Result<LeaderboardConfigurationList, GplayError> {
+        let mut request = self
+            .client
+            .get(format!(
+                "{}/{application_id}/leaderboards",
+                Self::GAMES_CONFIGURATION_URL,
+                application_id = application_id
+            ))
+            .bearer_auth(self.token.as_str());
+
+        if let Some(page_token) = page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+
+        self.get_response::<LeaderboardConfigurationList>(self.send(request).await?)
+            .await
+    }
+
+    // Send a request, waiting and retrying a few times if the API rate limits us. Requests
+    // with streaming bodies cannot be cloned and so are never retried.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, GplayError> {
+        let mut request = request;
+        let mut num_retries = 0;
+
+        loop {
+            let retry_request = request.try_clone();
+            let response = self.execute(request).await?;
+            let status = response.status();
+
+            let retry_request = match retry_request {
+                Some(retry_request)
+                    if num_retries < Self::MAX_RATE_LIMIT_RETRIES
+                        && (status == StatusCode::TOO_MANY_REQUESTS
+                            || status == StatusCode::FORBIDDEN) =>
+                {
+                    retry_request
+                }
+                _ => return Ok(response),
+            };
+            let headers = response.headers().clone();
+            let retry_after = headers
+                .get(RETRY_AFTER)
+                .and_then(|retry_after| retry_after.to_str().ok());
+            let body = response.bytes().await?;
+            let error = serde_json::from_slice::<ErrorResponse>(&body).ok();
+
+            match rate_limit::retry_delay(status, retry_after, error.as_ref()) {
+                Some(delay) => {
+                    warning!(
+                        self.log,
+                        "Rate limited by the API, retrying in {} seconds",
+                        delay.as_secs()
+                    );
+                    tokio::time::sleep(delay).await;
+                    num_retries += 1;
+                    request = retry_request;
+                }
+                // Not rate limited, so hand back the error response we consumed
+                None => {
+                    let mut response = http::Response::builder()
+                        .status(status)
+                        .body(body)
+                        .map_err(|err| GplayError::Other(err.to_string()))?;
+
+                    *response.headers_mut() = headers;
+
+                    return Ok(response.into());
+                }
+            }
+        }
+    }
+
+    // Send a request once, recording it and its response if a recorder was given
+    async fn execute(&self, request: RequestBuilder) -> Result<Response, GplayError> {
+        let recorder = match self.recorder {
+            Some(recorder) => recorder,
+            None => return Ok(request.send().await?),
+        };
+        let request = request.build()?;
+        let method = request.method().to_string();
+        let url = request.url().to_string();
+        let request_body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|bytes| bytes.to_vec());
+        let response = self.client.execute(request).await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await?;
+
+        recorder.record(
+            &method,
+            &url,
+            request_body.as_deref(),
+            status.as_u16(),
+            &body,
+        );
+
+        // Hand back a response with the body we consumed
+        let mut response = http::Response::builder()
+            .status(status)
+            .body(body)
+            .map_err(|err| GplayError::Other(err.to_string()))?;
+
+        *response.headers_mut() = headers;
+
+        Ok(response.into())
+    }
+
+    // Can we use PhantomData here?  Check the length of the returned body and return that instead?
+    async fn get_response<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        response: Response,
+    ) -> Result<T, GplayError> {
+        let status = response.status();
+
+        if status.is_success() {
+            if self.strict {
+                let raw = response.json::<serde_json::Value>().await?;
+                let value = serde_json::from_value::<T>(raw.clone())?;
+
+                for field in strict::unknown_fields(&raw, &serde_json::to_value(&value)?) {
+                    warning!(self.log, "Unknown field '{}' in API response", field);
+                }
+
+                Ok(value)
+            } else {
+                Ok(response.json::<T>().await?)
+            }
+        } else {
+            Err(Self::response_error(response).await)
+        }
+    }
+
+    async fn response_error(response: Response) -> GplayError {
+        let status = response.status();
+        let error = response
+            .json::<ErrorResponse>()
+            .await
+            .ok()
+            .map(|error| error.error);
+
+        GplayError::from(ResponseError { status, error })
+    }
+
+    // Resumable uploads answer 308 until the last byte arrives, then with the uploaded bundle
+    async fn get_upload_progress<T: for<'de> Deserialize<'de> + Serialize>(
+        &self,
+        response: Response,
+    ) -> Result<UploadProgress<T>, GplayError> {
+        if response.status() == StatusCode::PERMANENT_REDIRECT {
+            let range = response
+                .headers()
+                .get(RANGE)
+                .and_then(|range| range.to_str().ok());
+
+            return Ok(UploadProgress::Received(parse_received(range)?));
+        }
+
+        Ok(UploadProgress::Complete(
+            self.get_response::<T>(response).await?,
+        ))
+    }
+
+    async fn get_empty_response(response: Response) -> Result<(), GplayError> {
+        let status = response.status();
+
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(Self::response_error(response).await)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::StepContext;
+
+    #[test]
+    fn test_response_error_caused() {
+        let error: ErrorResponse = serde_json::from_str(
+            r#"{ "error": { "code": 400, "message": "This Edit has expired.", "errors": [{ "reason": "editExpired" }] } }"#,
+        )
+        .unwrap();
+        let result: Result<(), Box<dyn Error>> = Err(Box::new(ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            error: Some(error.error),
+        }));
+        let err = result
+            .step(|| "Unable to commit edit '42'".to_string())
+            .unwrap_err();
+
+        assert!(ResponseError::caused(err.as_ref(), "editExpired"));
+        assert!(!ResponseError::caused(err.as_ref(), "editAlreadyCommitted"));
+        assert_eq!(err.source().unwrap().to_string(), "This Edit has expired.");
+    }
+
+    #[test]
+    fn test_parse_received() {
+        assert_eq!(parse_received(Some("bytes=0-262143")).unwrap(), 262144);
+        assert_eq!(parse_received(None).unwrap(), 0);
+        assert!(parse_received(Some("bytes=42-")).is_err());
+    }
+}
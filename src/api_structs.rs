@@ -1,47 +1,631 @@
+//! Models for the Google Play Android Publisher v3 REST resources used by gplay.
+//! See <https://developers.google.com/android-publisher/api-ref/rest>.
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize)]
+/// An `AppEdit` returned when an edit is inserted
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct EditInsert {
     pub id: String,
+    #[serde(rename = "expiryTimeSeconds", skip_serializing_if = "Option::is_none")]
+    pub expiry_time_seconds: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Bundle {
     #[serde(rename = "versionCode")]
     pub version_code: i32,
-    pub sha256: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    /// Missing for some very old artifacts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl Bundle {
+    /// The SHA-256 hash, or a placeholder when the API didn't return one
+    pub fn sha256_or_unknown(&self) -> &str {
+        self.sha256.as_deref().unwrap_or("unknown")
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct EditBundlesList {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default)]
     pub bundles: Vec<Bundle>,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Apk {
+    #[serde(rename = "versionCode")]
+    pub version_code: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary: Option<ApkBinary>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ApkBinary {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha1: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ApksList {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub apks: Vec<Apk>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DeobfuscationFile {
+    /// `proguard` or `nativeCode`
+    #[serde(rename = "symbolType", skip_serializing_if = "Option::is_none")]
+    pub symbol_type: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DeobfuscationFilesUploadResponse {
+    #[serde(rename = "deobfuscationFile", skip_serializing_if = "Option::is_none")]
+    pub deobfuscation_file: Option<DeobfuscationFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct ErrorResponse {
     pub error: ApiError,
 }
 
-#[derive(Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct ApiError {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<u16>,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<ApiErrorDetail>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ApiErrorDetail {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct TracksList {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default)]
     pub tracks: Vec<Track>,
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Track {
     #[serde(rename = "track")]
     pub name: String,
+    #[serde(default)]
     pub releases: Vec<Release>,
 }
 
-#[derive(Deserialize, Serialize)]
+impl Track {
+    pub fn new(name: impl Into<String>, releases: Vec<Release>) -> Track {
+        Track {
+            name: name.into(),
+            releases,
+        }
+    }
+
+    /// The releases from most to least current: `inProgress`, then `halted`, `draft` and
+    /// `completed`, with releases of the same status ordered by their highest version code
+    pub fn releases_by_precedence(&self) -> Vec<&Release> {
+        let mut releases: Vec<&Release> = self.releases.iter().collect();
+
+        releases.sort_by_key(|release| {
+            (
+                Release::STATUS_PRECEDENCE
+                    .iter()
+                    .position(|status| *status == release.status)
+                    .unwrap_or(Release::STATUS_PRECEDENCE.len()),
+                std::cmp::Reverse(release.max_version_code()),
+            )
+        });
+
+        releases
+    }
+
+    /// The release that a command acting on a single release of the track should use, which is
+    /// the most current one, or the most current one with the given status
+    pub fn select_release(&self, status: Option<&str>) -> Option<&Release> {
+        self.releases_by_precedence()
+            .into_iter()
+            .find(|release| status.map_or(true, |status| release.status == status))
+    }
+
+    /// The track's releases once a new release is added to it. A staged (`inProgress` or
+    /// `halted`) release is rolled out over the `completed` release, which has to stay on the
+    /// track, and replaces the other releases. Any other release replaces all of them.
+    pub fn releases_with(&self, release: Release) -> Vec<Release> {
+        let mut releases = vec![];
+
+        if matches!(release.status.as_str(), "inProgress" | "halted") {
+            releases.extend(
+                self.releases
+                    .iter()
+                    .filter(|other| other.status == "completed")
+                    .cloned(),
+            );
+        }
+
+        releases.insert(0, release);
+
+        releases
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Release {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub status: String,
-    #[serde(rename = "versionCodes")]
+    #[serde(rename = "versionCodes", skip_serializing_if = "Option::is_none")]
     pub version_codes: Option<Vec<String>>,
+    #[serde(rename = "releaseNotes", skip_serializing_if = "Option::is_none")]
+    pub release_notes: Option<Vec<LocalizedText>>,
+    #[serde(rename = "userFraction", skip_serializing_if = "Option::is_none")]
+    pub user_fraction: Option<f64>,
+    #[serde(rename = "countryTargeting", skip_serializing_if = "Option::is_none")]
+    pub country_targeting: Option<CountryTargeting>,
+    #[serde(
+        rename = "inAppUpdatePriority",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub in_app_update_priority: Option<i32>,
+}
+
+impl Release {
+    /// Release statuses, from the most to the least current
+    pub const STATUS_PRECEDENCE: &'static [&'static str] =
+        &["inProgress", "halted", "draft", "completed"];
+
+    pub fn builder() -> ReleaseBuilder {
+        ReleaseBuilder::default()
+    }
+
+    pub fn max_version_code(&self) -> Option<i64> {
+        self.version_codes
+            .iter()
+            .flatten()
+            .filter_map(|version_code| version_code.parse::<i64>().ok())
+            .max()
+    }
+}
+
+/// Builds a [`Release`], which defaults to a `draft` with no version codes
+#[derive(Debug, Clone, Default)]
+pub struct ReleaseBuilder {
+    name: Option<String>,
+    status: Option<String>,
+    version_codes: Option<Vec<String>>,
+    release_notes: Option<Vec<LocalizedText>>,
+    user_fraction: Option<f64>,
+    country_targeting: Option<CountryTargeting>,
+    in_app_update_priority: Option<i32>,
+}
+
+impl ReleaseBuilder {
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn status(mut self, status: impl Into<String>) -> Self {
+        self.status = Some(status.into());
+        self
+    }
+
+    pub fn version_code(mut self, version_code: i32) -> Self {
+        self.version_codes
+            .get_or_insert_with(Vec::new)
+            .push(version_code.to_string());
+        self
+    }
+
+    pub fn release_note(mut self, language: impl Into<String>, text: impl Into<String>) -> Self {
+        self.release_notes
+            .get_or_insert_with(Vec::new)
+            .push(LocalizedText {
+                language: language.into(),
+                text: text.into(),
+            });
+        self
+    }
+
+    pub fn user_fraction(mut self, user_fraction: f64) -> Self {
+        self.user_fraction = Some(user_fraction);
+        self
+    }
+
+    pub fn country_targeting(mut self, country_targeting: CountryTargeting) -> Self {
+        self.country_targeting = Some(country_targeting);
+        self
+    }
+
+    pub fn in_app_update_priority(mut self, priority: i32) -> Self {
+        self.in_app_update_priority = Some(priority);
+        self
+    }
+
+    pub fn build(self) -> Release {
+        Release {
+            name: self.name,
+            status: self.status.unwrap_or_else(|| "draft".to_string()),
+            version_codes: self.version_codes,
+            release_notes: self.release_notes,
+            user_fraction: self.user_fraction,
+            country_targeting: self.country_targeting,
+            in_app_update_priority: self.in_app_update_priority,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LocalizedText {
+    pub language: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CountryTargeting {
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(rename = "includeRestOfWorld", default)]
+    pub include_rest_of_world: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReviewsList {
+    #[serde(default)]
+    pub reviews: Vec<Review>,
+    #[serde(rename = "tokenPagination", skip_serializing_if = "Option::is_none")]
+    pub token_pagination: Option<TokenPagination>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TokenPagination {
+    #[serde(rename = "nextPageToken", skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Review {
+    #[serde(rename = "reviewId")]
+    pub review_id: String,
+    #[serde(rename = "authorName", skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    #[serde(default)]
+    pub comments: Vec<Comment>,
+}
+
+impl Review {
+    pub fn user_comment(&self) -> Option<&UserComment> {
+        self.comments
+            .iter()
+            .find_map(|comment| comment.user_comment.as_ref())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Comment {
+    #[serde(rename = "userComment", skip_serializing_if = "Option::is_none")]
+    pub user_comment: Option<UserComment>,
+    #[serde(rename = "developerComment", skip_serializing_if = "Option::is_none")]
+    pub developer_comment: Option<DeveloperComment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UserComment {
+    #[serde(default)]
+    pub text: String,
+    #[serde(rename = "starRating", default)]
+    pub star_rating: i32,
+    #[serde(rename = "reviewerLanguage", skip_serializing_if = "Option::is_none")]
+    pub reviewer_language: Option<String>,
+    #[serde(rename = "appVersionCode", skip_serializing_if = "Option::is_none")]
+    pub app_version_code: Option<i32>,
+    #[serde(rename = "appVersionName", skip_serializing_if = "Option::is_none")]
+    pub app_version_name: Option<String>,
+    #[serde(rename = "lastModified", skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DeveloperComment {
+    #[serde(default)]
+    pub text: String,
+    #[serde(rename = "lastModified", skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Timestamp {
+    #[serde(default)]
+    pub seconds: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nanos: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReviewsReplyRequest {
+    #[serde(rename = "replyText")]
+    pub reply_text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReviewsReplyResponse {
+    pub result: ReviewReplyResult,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ReviewReplyResult {
+    #[serde(rename = "replyText")]
+    pub reply_text: String,
+    #[serde(rename = "lastEdited", skip_serializing_if = "Option::is_none")]
+    pub last_edited: Option<Timestamp>,
+}
+
+#[cfg(feature = "games")]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AchievementConfigurationList {
+    #[serde(default)]
+    pub items: Vec<AchievementConfiguration>,
+    #[serde(rename = "nextPageToken", skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+}
+
+#[cfg(feature = "games")]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct AchievementConfiguration {
+    pub id: String,
+    #[serde(rename = "achievementType", skip_serializing_if = "Option::is_none")]
+    pub achievement_type: Option<String>,
+    #[serde(rename = "initialState", skip_serializing_if = "Option::is_none")]
+    pub initial_state: Option<String>,
+    #[serde(rename = "stepsToUnlock", skip_serializing_if = "Option::is_none")]
+    pub steps_to_unlock: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub draft: Option<GamesConfigurationDetail>,
+}
+
+#[cfg(feature = "games")]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LeaderboardConfigurationList {
+    #[serde(default)]
+    pub items: Vec<LeaderboardConfiguration>,
+    #[serde(rename = "nextPageToken", skip_serializing_if = "Option::is_none")]
+    pub next_page_token: Option<String>,
+}
+
+#[cfg(feature = "games")]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LeaderboardConfiguration {
+    pub id: String,
+    #[serde(rename = "scoreOrder", skip_serializing_if = "Option::is_none")]
+    pub score_order: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub draft: Option<GamesConfigurationDetail>,
+}
+
+/// The draft or published details of an achievement or leaderboard
+#[cfg(feature = "games")]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct GamesConfigurationDetail {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<LocalizedStringBundle>,
+}
+
+#[cfg(feature = "games")]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LocalizedStringBundle {
+    #[serde(default)]
+    pub translations: Vec<LocalizedString>,
+}
+
+#[cfg(feature = "games")]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LocalizedString {
+    pub locale: String,
+    pub value: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_releases_with() {
+        let track = Track::new(
+            "production",
+            vec![
+                Release::builder()
+                    .status("completed")
+                    .version_code(240)
+                    .build(),
+                Release::builder().status("draft").version_code(241).build(),
+                Release::builder()
+                    .status("inProgress")
+                    .version_code(241)
+                    .user_fraction(0.1)
+                    .build(),
+            ],
+        );
+        let staged = Release::builder()
+            .status("inProgress")
+            .version_code(242)
+            .user_fraction(0.05)
+            .build();
+        let completed = Release::builder()
+            .status("completed")
+            .version_code(242)
+            .build();
+
+        // The live release stays while the new one is rolled out, superseding the others
+        assert_eq!(
+            track.releases_with(staged.clone()),
+            vec![staged, track.releases[0].clone()]
+        );
+        assert_eq!(track.releases_with(completed.clone()), vec![completed]);
+    }
+
+    #[test]
+    fn track_select_release() {
+        let track = Track::new(
+            "production",
+            vec![
+                Release::builder()
+                    .status("completed")
+                    .version_code(240)
+                    .build(),
+                Release::builder().status("draft").version_code(242).build(),
+                Release::builder()
+                    .status("inProgress")
+                    .version_code(241)
+                    .build(),
+                Release::builder()
+                    .status("completed")
+                    .version_code(239)
+                    .build(),
+            ],
+        );
+        let version_codes = |releases: Vec<&Release>| {
+            releases
+                .iter()
+                .map(|release| release.max_version_code().unwrap())
+                .collect::<Vec<i64>>()
+        };
+
+        assert_eq!(
+            version_codes(track.releases_by_precedence()),
+            vec![241, 242, 240, 239]
+        );
+        assert_eq!(
+            track.select_release(None).unwrap().max_version_code(),
+            Some(241)
+        );
+        assert_eq!(
+            track
+                .select_release(Some("completed"))
+                .unwrap()
+                .max_version_code(),
+            Some(240)
+        );
+        assert_eq!(track.select_release(Some("halted")), None);
+    }
+
+    #[test]
+    fn track_round_trip() {
+        let json = r#"{
+            "track": "production",
+            "releases": [
+                {
+                    "name": "2.4.1",
+                    "versionCodes": ["241"],
+                    "releaseNotes": [{ "language": "en-US", "text": "Bug fixes" }],
+                    "status": "inProgress",
+                    "userFraction": 0.2,
+                    "countryTargeting": { "countries": ["US", "CA"], "includeRestOfWorld": false },
+                    "inAppUpdatePriority": 3
+                },
+                { "versionCodes": ["240"], "status": "completed" }
+            ]
+        }"#;
+        let track: Track = serde_json::from_str(json).unwrap();
+
+        assert_eq!(track.name, "production");
+        assert_eq!(track.releases.len(), 2);
+        assert_eq!(track.releases[0].user_fraction, Some(0.2));
+        assert_eq!(track.releases[1].name, None);
+
+        let round_tripped: Track =
+            serde_json::from_str(&serde_json::to_string(&track).unwrap()).unwrap();
+
+        assert_eq!(round_tripped, track);
+    }
+
+    #[test]
+    fn bundles_list_round_trip() {
+        let json = r#"{
+            "kind": "androidpublisher#bundlesListResponse",
+            "bundles": [{ "versionCode": 12, "sha1": "ab12", "sha256": "cd34" }]
+        }"#;
+        let list: EditBundlesList = serde_json::from_str(json).unwrap();
+
+        assert_eq!(list.bundles[0].version_code, 12);
+        assert_eq!(list.bundles[0].sha256_or_unknown(), "cd34");
+        assert_eq!(
+            serde_json::from_str::<EditBundlesList>(&serde_json::to_string(&list).unwrap())
+                .unwrap(),
+            list
+        );
+        // Very old bundles may have no SHA-256 hash
+        assert_eq!(
+            serde_json::from_str::<Bundle>(r#"{ "versionCode": 1 }"#)
+                .unwrap()
+                .sha256_or_unknown(),
+            "unknown"
+        );
+        // An app with no bundles omits the list entirely
+        assert!(serde_json::from_str::<EditBundlesList>("{}")
+            .unwrap()
+            .bundles
+            .is_empty());
+    }
+
+    #[test]
+    fn error_response() {
+        let json = r#"{
+            "error": {
+                "code": 403,
+                "message": "The caller does not have permission",
+                "status": "PERMISSION_DENIED",
+                "errors": [{ "message": "The caller does not have permission", "domain": "global", "reason": "forbidden" }]
+            }
+        }"#;
+        let response: ErrorResponse = serde_json::from_str(json).unwrap();
+
+        assert_eq!(response.error.code, Some(403));
+        assert_eq!(
+            response.error.errors[0].reason.as_deref(),
+            Some("forbidden")
+        );
+    }
+
+    #[test]
+    fn release_builder() {
+        let release = Release::builder()
+            .name("2.4.1 hotfix")
+            .version_code(241)
+            .release_note("en-US", "Fixes")
+            .build();
+
+        assert_eq!(release.status, "draft");
+        assert_eq!(release.version_codes, Some(vec!["241".to_string()]));
+        assert_eq!(
+            serde_json::to_value(&release).unwrap(),
+            serde_json::json!({
+                "name": "2.4.1 hotfix",
+                "status": "draft",
+                "versionCodes": ["241"],
+                "releaseNotes": [{ "language": "en-US", "text": "Fixes" }]
+            })
+        );
+    }
 }
@@ -1,12 +1,42 @@
 use core::fmt::Arguments;
-use gplay::{error, GplayLog, GplayTool};
-use yansi::Paint;
+use gplay::{error, DeadlineExceeded, GplayLog, GplayTool, TimestampLog};
+use std::error::Error;
+use yansi::{Color, Style};
 
-struct GplayLogger;
+/// Styles for warnings and errors, chosen with the GPLAY_THEME environment variable
+struct Theme {
+    warning: Style,
+    error: Style,
+}
+
+impl Theme {
+    fn from_env() -> Theme {
+        match std::env::var("GPLAY_THEME").as_deref() {
+            Ok("high-contrast") => Theme {
+                warning: Style::new(Color::Black).bg(Color::Yellow).bold(),
+                error: Style::new(Color::White).bg(Color::Red).bold(),
+            },
+            Ok("monochrome") => Theme {
+                warning: Style::default(),
+                error: Style::default().bold(),
+            },
+            _ => Theme {
+                warning: Style::new(Color::Yellow),
+                error: Style::new(Color::Red),
+            },
+        }
+    }
+}
+
+struct GplayLogger {
+    theme: Theme,
+}
 
 impl GplayLogger {
     fn new() -> GplayLogger {
-        GplayLogger {}
+        GplayLogger {
+            theme: Theme::from_env(),
+        }
     }
 }
 
@@ -15,19 +45,38 @@ impl GplayLog for GplayLogger {
         println!("{}", args);
     }
     fn warning(self: &Self, args: Arguments) {
-        eprintln!("{}", Paint::yellow(format!("warning: {}", args)));
+        eprintln!("{}", self.theme.warning.paint(format!("warning: {}", args)));
     }
     fn error(self: &Self, args: Arguments) {
-        eprintln!("{}", Paint::red(format!("error: {}", args)));
+        eprintln!("{}", self.theme.error.paint(format!("error: {}", args)));
     }
 }
 
 #[tokio::main]
 async fn main() {
     let logger = GplayLogger::new();
+    let mut tool = GplayTool::new(&logger);
+
+    if let Err(error) = tool.run(std::env::args_os()).await {
+        let mut message = error.to_string();
+        let mut source = error.source();
+
+        while let Some(cause) = source {
+            message.push_str(&format!("\n  caused by: {}", cause));
+            source = cause.source();
+        }
+
+        // The tool's own log has gone by now, so stamp the last line the same way it would have
+        if tool.timestamps() {
+            error!(TimestampLog::new(&logger), "{}", message);
+        } else {
+            error!(logger, "{}", message);
+        }
+
+        if DeadlineExceeded::caused(error.as_ref()) {
+            std::process::exit(DeadlineExceeded::EXIT_CODE);
+        }
 
-    if let Err(error) = GplayTool::new(&logger).run(std::env::args_os()).await {
-        error!(logger, "{}", error);
         std::process::exit(1);
     }
 }
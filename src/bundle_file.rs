@@ -0,0 +1,200 @@
+use crate::bundle_info::{self, BundleModule, BundleVersion};
+use crate::GplayError;
+use bytes::Bytes;
+use easy_error::ResultExt;
+use std::error::Error;
+use std::io::{Cursor, Read, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+
+/// A reader for the body of an upload
+pub type BundleReader = Box<dyn AsyncRead + Send + Sync + Unpin>;
+
+#[derive(Debug, Clone)]
+enum Source {
+    File(PathBuf),
+    Buffer(Bytes),
+}
+
+/// A bundle, or another artifact such as an APK, to be uploaded. Files are streamed from disk
+/// each time they are uploaded so that large bundles are never held in memory. Standard input
+/// can only be read once, so a bundle from there is copied to a temporary file first, which
+/// is deleted when the last clone of the `BundleFile` is dropped.
+#[derive(Debug, Clone)]
+pub struct BundleFile {
+    source: Source,
+    size: u64,
+    _temp_file: Option<Arc<NamedTempFile>>,
+}
+
+impl BundleFile {
+    /// Open a file, where a path of '-' means read it from standard input
+    pub fn open(path: &Path) -> Result<BundleFile, GplayError> {
+        let io_error = |context: &str| {
+            let context = context.to_string();
+
+            move |err| GplayError::Context {
+                context,
+                source: Box::new(GplayError::Io(err)),
+            }
+        };
+
+        if path == Path::new("-") {
+            Self::from_reader(std::io::stdin().lock())
+                .map_err(io_error("Unable to read standard input"))
+        } else {
+            let metadata = std::fs::metadata(path).map_err(io_error("Unable to read file"))?;
+
+            if !metadata.is_file() {
+                return Err(GplayError::Other(format!(
+                    "'{}' is not a file",
+                    path.to_string_lossy()
+                )));
+            }
+
+            Ok(BundleFile {
+                source: Source::File(path.to_path_buf()),
+                size: metadata.len(),
+                _temp_file: None,
+            })
+        }
+    }
+
+    /// Copy a bundle that can only be read once, such as standard input, to a temporary file
+    pub fn from_reader(mut reader: impl Read) -> Result<BundleFile, std::io::Error> {
+        let mut temp_file = NamedTempFile::new()?;
+        let size = std::io::copy(&mut reader, &mut temp_file)?;
+
+        Ok(BundleFile {
+            source: Source::File(temp_file.path().to_path_buf()),
+            size,
+            _temp_file: Some(Arc::new(temp_file)),
+        })
+    }
+
+    pub fn from_bytes(byte_buf: Bytes) -> BundleFile {
+        BundleFile {
+            size: byte_buf.len() as u64,
+            source: Source::Buffer(byte_buf),
+            _temp_file: None,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The modules in the bundle, see [`bundle_info::modules`]
+    pub fn modules(&self) -> Result<Vec<BundleModule>, Box<dyn Error>> {
+        match &self.source {
+            Source::File(path) => bundle_info::modules(std::fs::File::open(path)?),
+            Source::Buffer(byte_buf) => bundle_info::modules(Cursor::new(byte_buf)),
+        }
+    }
+
+    /// The version from the bundle manifest, see [`bundle_info::version`]
+    pub fn version(&self) -> Result<BundleVersion, Box<dyn Error>> {
+        match &self.source {
+            Source::File(path) => bundle_info::version(std::fs::File::open(path)?),
+            Source::Buffer(byte_buf) => bundle_info::version(Cursor::new(byte_buf)),
+        }
+    }
+
+    /// Read the bundle from the start, for one attempt at uploading it
+    pub async fn reader(&self) -> Result<BundleReader, Box<dyn Error>> {
+        Ok(match &self.source {
+            Source::File(path) => Box::new(
+                tokio::fs::File::open(path)
+                    .await
+                    .context("Unable to open file")?,
+            ),
+            Source::Buffer(byte_buf) => Box::new(Cursor::new(byte_buf.clone())),
+        })
+    }
+
+    /// Read `len` bytes of the bundle from `offset`, for one chunk of a resumable upload
+    pub async fn read_chunk(&self, offset: u64, len: u64) -> Result<Bytes, Box<dyn Error>> {
+        match &self.source {
+            Source::File(path) => {
+                let mut file = tokio::fs::File::open(path)
+                    .await
+                    .context("Unable to open file")?;
+                let mut chunk = vec![0; len as usize];
+
+                file.seek(SeekFrom::Start(offset)).await?;
+                file.read_exact(&mut chunk)
+                    .await
+                    .context("Unable to read file")?;
+
+                Ok(Bytes::from(chunk))
+            }
+            Source::Buffer(byte_buf) => {
+                Ok(byte_buf.slice(offset as usize..(offset + len) as usize))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reader() {
+        let path = std::env::temp_dir().join(format!("gplay-bundle-{}.aab", std::process::id()));
+
+        std::fs::write(&path, b"PK\x03\x04").unwrap();
+
+        let bundle_file = BundleFile::open(&path).unwrap();
+        let mut contents = vec![];
+
+        assert_eq!(bundle_file.size(), 4);
+
+        // Each upload attempt reads the whole bundle again
+        for _ in 0..2 {
+            contents.clear();
+            tokio_test::block_on(async {
+                bundle_file
+                    .reader()
+                    .await
+                    .unwrap()
+                    .read_to_end(&mut contents)
+                    .await
+            })
+            .unwrap();
+            assert_eq!(contents, b"PK\x03\x04");
+        }
+
+        assert_eq!(
+            tokio_test::block_on(bundle_file.read_chunk(1, 2)).unwrap(),
+            Bytes::from_static(b"K\x03")
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(BundleFile::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let bundle_file = BundleFile::from_reader(Cursor::new(b"PK\x03\x04".to_vec())).unwrap();
+        let temp_path = match &bundle_file.source {
+            Source::File(path) => path.clone(),
+            Source::Buffer(_) => panic!("A bundle read once should be streamed from a file"),
+        };
+        let clone = bundle_file.clone();
+
+        assert_eq!(bundle_file.size(), 4);
+        assert_eq!(
+            tokio_test::block_on(clone.read_chunk(1, 2)).unwrap(),
+            Bytes::from_static(b"K\x03")
+        );
+
+        // The temporary file lasts as long as any clone
+        drop(bundle_file);
+        assert!(temp_path.exists());
+        drop(clone);
+        assert!(!temp_path.exists());
+    }
+}
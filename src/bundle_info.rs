@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{Read, Seek};
+use zip::ZipArchive;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModuleKind {
+    Base,
+    Feature,
+    AssetPack,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BundleModule {
+    pub name: String,
+    pub kind: ModuleKind,
+    /// Total compressed size of the module's files within the bundle
+    pub size: u64,
+}
+
+/// List the modules in an Android App Bundle. Each module is a top level directory of the
+/// bundle zip containing a `manifest/` directory.
+pub fn modules<R: Read + Seek>(bundle: R) -> Result<Vec<BundleModule>, Box<dyn Error>> {
+    let mut archive = ZipArchive::new(bundle)?;
+    // Name -> (size, has manifest, has code or resources)
+    let mut dirs: BTreeMap<String, (u64, bool, bool)> = BTreeMap::new();
+
+    for index in 0..archive.len() {
+        let file = archive.by_index(index)?;
+        let (dir, path) = match file.name().split_once('/') {
+            Some(split) => split,
+            None => continue,
+        };
+        let entry = dirs.entry(dir.to_string()).or_default();
+
+        entry.0 += file.compressed_size();
+        entry.1 |= path.starts_with("manifest/");
+        entry.2 |= path.starts_with("dex/") || path == "resources.pb";
+    }
+
+    Ok(dirs
+        .into_iter()
+        .filter(|(_, (_, has_manifest, _))| *has_manifest)
+        .map(|(name, (size, _, has_code))| BundleModule {
+            kind: if name == "base" {
+                ModuleKind::Base
+            } else if has_code {
+                ModuleKind::Feature
+            } else {
+                // Asset packs carry only assets, never code or compiled resources
+                ModuleKind::AssetPack
+            },
+            name,
+            size,
+        })
+        .collect())
+}
+
+/// The `versionName` and `versionCode` from the base module manifest, if present
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BundleVersion {
+    pub version_name: Option<String>,
+    pub version_code: Option<String>,
+}
+
+/// Read the version attributes of the base module manifest, which bundles store as an
+/// aapt2 protobuf `XmlNode` rather than binary XML.
+pub fn version<R: Read + Seek>(bundle: R) -> Result<BundleVersion, Box<dyn Error>> {
+    let mut archive = ZipArchive::new(bundle)?;
+    let mut manifest = vec![];
+
+    archive
+        .by_name("base/manifest/AndroidManifest.xml")?
+        .read_to_end(&mut manifest)?;
+
+    let mut version = BundleVersion::default();
+
+    // XmlNode.element (1) -> XmlElement.attribute (4) -> XmlAttribute.name (2) and value (3)
+    for (field, element) in proto_fields(&manifest)? {
+        if field != 1 {
+            continue;
+        }
+
+        for (field, attribute) in proto_fields(element)? {
+            if field != 4 {
+                continue;
+            }
+
+            let mut name = None;
+            let mut value = None;
+
+            for (field, bytes) in proto_fields(attribute)? {
+                match field {
+                    2 => name = Some(String::from_utf8_lossy(bytes).into_owned()),
+                    3 => value = Some(String::from_utf8_lossy(bytes).into_owned()),
+                    _ => {}
+                }
+            }
+
+            match name.as_deref() {
+                Some("versionName") => version.version_name = value,
+                Some("versionCode") => version.version_code = value,
+                _ => {}
+            }
+        }
+    }
+
+    Ok(version)
+}
+
+// Split a protobuf message into its length-delimited fields, skipping fields of other wire types
+fn proto_fields(mut buf: &[u8]) -> Result<Vec<(u64, &[u8])>, Box<dyn Error>> {
+    let mut fields = vec![];
+
+    while !buf.is_empty() {
+        let key = read_varint(&mut buf)?;
+        let field = key >> 3;
+
+        match key & 7 {
+            0 => {
+                read_varint(&mut buf)?;
+            }
+            1 => buf = buf.get(8..).ok_or("Truncated protobuf field")?,
+            2 => {
+                let len = read_varint(&mut buf)? as usize;
+
+                if len > buf.len() {
+                    return Err("Truncated protobuf field".into());
+                }
+
+                let (bytes, rest) = buf.split_at(len);
+
+                fields.push((field, bytes));
+                buf = rest;
+            }
+            5 => buf = buf.get(4..).ok_or("Truncated protobuf field")?,
+            wire_type => return Err(format!("Unsupported protobuf wire type {}", wire_type).into()),
+        }
+    }
+
+    Ok(fields)
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64, Box<dyn Error>> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let current: &[u8] = *buf;
+        let (byte, rest) = current.split_first().ok_or("Truncated protobuf varint")?;
+
+        *buf = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err("Protobuf varint is too long".into())
+}
+
+impl std::fmt::Display for ModuleKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            ModuleKind::Base => "base",
+            ModuleKind::Feature => "feature",
+            ModuleKind::AssetPack => "asset pack",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::{write::FileOptions, ZipWriter};
+
+    #[test]
+    fn test_modules() {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+        for name in [
+            "BundleConfig.pb",
+            "META-INF/MANIFEST.MF",
+            "base/manifest/AndroidManifest.xml",
+            "base/dex/classes.dex",
+            "base/resources.pb",
+            "camera/manifest/AndroidManifest.xml",
+            "camera/dex/classes.dex",
+            "textures/manifest/AndroidManifest.xml",
+            "textures/assets/hd.bin",
+        ] {
+            writer.start_file(name, FileOptions::default()).unwrap();
+            writer.write_all(name.as_bytes()).unwrap();
+        }
+
+        let bundle = writer.finish().unwrap().into_inner();
+        let modules = modules(Cursor::new(&bundle)).unwrap();
+        let kinds: Vec<(&str, ModuleKind)> = modules
+            .iter()
+            .map(|module| (module.name.as_str(), module.kind))
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                ("base", ModuleKind::Base),
+                ("camera", ModuleKind::Feature),
+                ("textures", ModuleKind::AssetPack),
+            ]
+        );
+        assert!(modules.iter().all(|module| module.size > 0));
+    }
+
+    fn proto_field(field: u8, bytes: &[u8]) -> Vec<u8> {
+        let mut buf = vec![(field << 3) | 2, bytes.len() as u8];
+
+        buf.extend_from_slice(bytes);
+        buf
+    }
+
+    #[test]
+    fn test_version() {
+        let mut element = proto_field(3, b"manifest");
+
+        for (name, value) in [("versionCode", "10402"), ("versionName", "1.4.2")] {
+            let mut attribute = proto_field(1, b"http://schemas.android.com/apk/res/android");
+
+            attribute.extend(proto_field(2, name.as_bytes()));
+            attribute.extend(proto_field(3, value.as_bytes()));
+            element.extend(proto_field(4, &attribute));
+        }
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+        writer
+            .start_file("base/manifest/AndroidManifest.xml", FileOptions::default())
+            .unwrap();
+        writer.write_all(&proto_field(1, &element)).unwrap();
+
+        let bundle = writer.finish().unwrap().into_inner();
+
+        assert_eq!(
+            version(Cursor::new(&bundle)).unwrap(),
+            BundleVersion {
+                version_name: Some("1.4.2".to_string()),
+                version_code: Some("10402".to_string()),
+            }
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use crate::state_store::StateStore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// A cached response along with when it was stored, as stores don't all keep modified times
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<T> {
+    stored_secs: u64,
+    value: T,
+}
+
+/// A short-lived cache of API responses for read-only commands, keyed by package name and
+/// by the account that fetched them, as accounts can have different permissions
+pub struct ResponseCache {
+    store: Box<dyn StateStore>,
+    ttl: Duration,
+    refresh: bool,
+    default_account: Option<String>,
+}
+
+impl ResponseCache {
+    /// Create a cache in a state store. When `refresh` is set cached responses are ignored,
+    /// but fresh responses are still stored. A zero `ttl` turns caching off.
+    pub fn new(
+        store: Box<dyn StateStore>,
+        ttl: Duration,
+        refresh: bool,
+        default_account: Option<String>,
+    ) -> ResponseCache {
+        ResponseCache {
+            store,
+            ttl,
+            refresh,
+            default_account,
+        }
+    }
+
+    /// The account of the default credentials, for packages without their own
+    pub fn default_account(&self) -> Option<&str> {
+        self.default_account.as_deref()
+    }
+
+    fn dir(package_name: &str) -> String {
+        format!("responses/{}", package_name)
+    }
+
+    // Accounts are hashed so that their email addresses aren't written to a shared store
+    fn key(package_name: &str, account: &str, name: &str) -> String {
+        let account_hash: String = Sha256::digest(account.as_bytes())
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        format!("{}/{}/{}.json", Self::dir(package_name), account_hash, name)
+    }
+
+    fn now_secs() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs())
+    }
+
+    pub async fn get<T: DeserializeOwned>(
+        &self,
+        package_name: &str,
+        account: &str,
+        name: &str,
+    ) -> Option<T> {
+        if self.refresh || self.ttl.is_zero() {
+            return None;
+        }
+
+        let json = self
+            .store
+            .get(&Self::key(package_name, account, name))
+            .await
+            .ok()??;
+        let entry: CacheEntry<T> = serde_json::from_slice(&json).ok()?;
+
+        if Self::now_secs().saturating_sub(entry.stored_secs) > self.ttl.as_secs() {
+            return None;
+        }
+
+        Some(entry.value)
+    }
+
+    // Caching is best effort, so failures to write are ignored
+    pub async fn put<T: Serialize>(
+        &self,
+        package_name: &str,
+        account: &str,
+        name: &str,
+        value: &T,
+    ) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let entry = CacheEntry {
+            stored_secs: Self::now_secs(),
+            value,
+        };
+
+        if let Ok(json) = serde_json::to_vec(&entry) {
+            let _ = self
+                .store
+                .put(&Self::key(package_name, account, name), json)
+                .await;
+        }
+    }
+
+    /// Forget everything cached for a package by any account, e.g. after an edit has been
+    /// committed
+    pub async fn invalidate(&self, package_name: &str) {
+        let _ = self.store.remove_all(&Self::dir(package_name)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        let key = ResponseCache::key(
+            "com.example.app",
+            "ci@example.iam.gserviceaccount.com",
+            "tracks",
+        );
+
+        assert!(key.starts_with("responses/com.example.app/"));
+        assert!(key.ends_with("/tracks.json"));
+        assert!(!key.contains("example.iam"));
+        assert_ne!(
+            key,
+            ResponseCache::key(
+                "com.example.app",
+                "other@example.iam.gserviceaccount.com",
+                "tracks"
+            )
+        );
+    }
+}
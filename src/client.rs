@@ -0,0 +1,253 @@
+use crate::api_structs::{Bundle, LocalizedText, Track};
+use crate::commands::bundles::{check_release_notes, release_notes_or_default, release_settings};
+use crate::{upload, AndroidPublisherApi, GplayError, GplayTool, Outcome, UploadResult};
+use gcp_auth::{AuthenticationManager, Token};
+use reqwest::Client;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Options for [`GplayClient::upload_bundle`], with the same defaults as `gplay upload`
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    pub track_name: String,
+    /// A ProGuard or R8 mapping file to attach to the bundle
+    pub mapping_file: Option<PathBuf>,
+    /// The name of the release, instead of the version name and code from the bundle
+    pub release_name: Option<String>,
+    /// Release notes for each language, each of at most 500 characters, instead of the track's
+    /// notes file from the config file
+    pub release_notes: Vec<LocalizedText>,
+    /// The release status, instead of the track's default from the config file
+    pub status: Option<String>,
+    /// The fraction of users for a staged rollout, which makes the release `inProgress`
+    pub rollout_fraction: Option<f64>,
+    /// The timeout for each upload request
+    pub timeout: Duration,
+    /// Bundles larger than this are uploaded in chunks, and it must be a multiple of 256KiB
+    pub chunk_size: u64,
+    /// Limit the upload to this many bytes per second
+    pub max_upload_rate: Option<u64>,
+    /// Roll back the edit if the whole upload takes longer than this
+    pub edit_timeout: Option<Duration>,
+    pub allow_downgrade: bool,
+    pub ack_install_warning: bool,
+    /// Release the version already uploaded if the bundle's version code has been used
+    pub assign_if_exists: bool,
+}
+
+impl UploadOptions {
+    pub fn new(track_name: &str) -> UploadOptions {
+        UploadOptions {
+            track_name: track_name.to_string(),
+            mapping_file: None,
+            release_name: None,
+            release_notes: vec![],
+            status: None,
+            rollout_fraction: None,
+            timeout: Duration::from_secs(300),
+            chunk_size: upload::DEFAULT_CHUNK_SIZE,
+            max_upload_rate: None,
+            edit_timeout: None,
+            allow_downgrade: false,
+            ack_install_warning: false,
+            assign_if_exists: false,
+        }
+    }
+}
+
+/// Use gplay from other Rust tools. The methods do what the commands of the same name do but
+/// return what they find instead of printing it, although progress and warnings still go to
+/// the tool's log.
+pub struct GplayClient<'a> {
+    tool: GplayTool<'a>,
+    client: Client,
+    authentication_manager: AuthenticationManager,
+}
+
+impl<'a> GplayClient<'a> {
+    /// Authenticate with the tool's credentials, e.g. from [`GplayTool::from_credentials_json`]
+    pub async fn connect(tool: GplayTool<'a>) -> Result<GplayClient<'a>, GplayError> {
+        let credentials = tool.credentials.as_deref().ok_or_else(|| {
+            GplayError::Auth("The tool must be created with credentials to connect".to_string())
+        })?;
+        let authentication_manager = credentials
+            .authentication_manager()
+            .await
+            .map_err(|err| GplayError::from(err).or_auth())?;
+        let client = GplayClient {
+            client: GplayTool::new_client(&[], None)?,
+            authentication_manager,
+            tool,
+        };
+
+        // Fail now rather than on the first call if the credentials can't be used
+        client.token().await?;
+
+        Ok(client)
+    }
+
+    /// An OAuth token for the Android Publisher API. Tokens expire after about an hour, so
+    /// a new one is fetched when the last one is about to expire, and every call gets one
+    /// this way.
+    pub async fn token(&self) -> Result<Token, GplayError> {
+        self.authentication_manager
+            .get_token(&[GplayTool::ANDROID_PUBLISHER_SCOPE])
+            .await
+            .map_err(|err| GplayError::Auth(format!("Unable to get an OAuth token: {}", err)))
+    }
+
+    /// The underlying API client, for endpoints that have no method here, using a token from
+    /// [`GplayClient::token`]
+    pub fn api<'t>(&'t self, token: &'t Token) -> AndroidPublisherApi<'t> {
+        self.tool.api(&self.client, token)
+    }
+
+    pub async fn list_bundles(&self, package_name: &str) -> Result<Vec<Bundle>, GplayError> {
+        let token = self.token().await?;
+        let (edit_bundles_list, _) = self
+            .tool
+            .read_bundles_and_tracks(&self.client, &token, package_name)
+            .await?;
+
+        Ok(edit_bundles_list.bundles)
+    }
+
+    pub async fn list_tracks(&self, package_name: &str) -> Result<Vec<Track>, GplayError> {
+        let token = self.token().await?;
+
+        Ok(self
+            .tool
+            .read_tracks(&self.client, &token, package_name)
+            .await?
+            .tracks)
+    }
+
+    pub async fn get_track(
+        &self,
+        package_name: &str,
+        track_name: &str,
+    ) -> Result<Track, GplayError> {
+        self.list_tracks(package_name)
+            .await?
+            .into_iter()
+            .find(|track| track.name == track_name)
+            .ok_or_else(|| GplayError::Other(format!("Track '{}' not found", track_name)))
+    }
+
+    /// Upload a bundle and release it to a track in a new edit, which is committed
+    pub async fn upload_bundle(
+        &self,
+        package_name: &str,
+        aab_file: &Path,
+        options: &UploadOptions,
+    ) -> Result<Outcome<UploadResult>, GplayError> {
+        if options.chunk_size % upload::CHUNK_GRANULARITY != 0 {
+            return Err(GplayError::Other(
+                "The chunk size must be a multiple of 256KiB".to_string(),
+            ));
+        }
+
+        let track_defaults = release_settings(
+            self.tool.config.track_defaults(&options.track_name),
+            options.status.as_deref(),
+            options.rollout_fraction,
+            !options.release_notes.is_empty(),
+        )?;
+        let release_notes =
+            release_notes_or_default(options.release_notes.clone(), &track_defaults)?;
+
+        check_release_notes(&release_notes)?;
+
+        let token = self.token().await?;
+
+        self.tool
+            .upload_bundle(
+                &self.client,
+                &token,
+                package_name,
+                aab_file,
+                options.mapping_file.as_deref(),
+                &options.track_name,
+                options.release_name.as_deref(),
+                &release_notes,
+                &track_defaults,
+                options.timeout.as_secs(),
+                options.chunk_size,
+                options.max_upload_rate,
+                options
+                    .edit_timeout
+                    .map(|edit_timeout| edit_timeout.as_secs()),
+                options.allow_downgrade,
+                options.ack_install_warning,
+                options.assign_if_exists,
+            )
+            .await
+            .map_err(GplayError::from)
+    }
+
+    /// Roll out the track's staged release to all users
+    pub async fn complete_release(
+        &self,
+        package_name: &str,
+        track_name: &str,
+    ) -> Result<(), GplayError> {
+        let token = self.token().await?;
+
+        self.tool
+            .complete_release(&self.client, &token, package_name, track_name, None)
+            .await
+            .map_err(GplayError::from)
+    }
+
+    /// Change the fraction of users that the track's staged release is rolled out to
+    pub async fn set_rollout(
+        &self,
+        package_name: &str,
+        track_name: &str,
+        fraction: f64,
+    ) -> Result<(), GplayError> {
+        if !(fraction > 0.0 && fraction < 1.0) {
+            return Err(GplayError::Other(format!(
+                "{} is not a fraction between 0 and 1",
+                fraction
+            )));
+        }
+
+        let token = self.token().await?;
+
+        self.tool
+            .set_rollout(
+                &self.client,
+                &token,
+                package_name,
+                track_name,
+                fraction,
+                None,
+            )
+            .await
+            .map_err(GplayError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GplayLog;
+    use core::fmt::Arguments;
+
+    struct TestLogger;
+
+    impl GplayLog for TestLogger {
+        fn output(&self, _args: Arguments) {}
+        fn warning(&self, _args: Arguments) {}
+        fn error(&self, _args: Arguments) {}
+    }
+
+    #[test]
+    fn test_connect_without_credentials() {
+        let log = TestLogger;
+        let result = tokio_test::block_on(GplayClient::connect(GplayTool::new(&log)));
+
+        assert!(matches!(result, Err(GplayError::Auth(_))));
+    }
+}
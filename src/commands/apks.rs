@@ -0,0 +1,208 @@
+use crate::api::ArtifactKind;
+use crate::api_structs::*;
+use crate::bundle_file::BundleFile;
+use crate::messages::MessageId;
+use crate::step::StepContext;
+use crate::table::Table;
+use crate::upload::{ArtifactUploader, Sha256Hash};
+use crate::{output, GplayTool};
+use clap::{Args, Subcommand};
+use gcp_auth::Token;
+use reqwest::Client;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Subcommand)]
+pub enum ApksCommands {
+    /// Lists uploaded APK versions and the tracks that reference them
+    List,
+    /// Upload an APK and release it to a track, for apps that don't ship bundles
+    Upload(UploadApkArgs),
+}
+
+#[derive(Args)]
+pub struct UploadApkArgs {
+    /// The APK file to upload
+    #[arg(short = 'a', long = "apk-file", value_name = "APK-FILE", value_hint = clap::ValueHint::FilePath)]
+    pub apk_file: PathBuf,
+    /// The name of the track to add the APK to
+    #[arg(short = 'n', long = "track-name", value_name = "NAME")]
+    pub track_name: String,
+    /// The name of the release, instead of the version code
+    #[arg(long = "release-name", value_name = "NAME")]
+    pub release_name: Option<String>,
+    /// The timeout for the upload in seconds
+    #[arg(
+        short = 't',
+        long = "timeout",
+        value_name = "TIMEOUT-SECS",
+        default_value = "300"
+    )]
+    pub timeout_secs: u64,
+}
+
+impl GplayTool<'_> {
+    pub(crate) async fn upload_apk(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        args: &UploadApkArgs,
+    ) -> Result<(), Box<dyn Error>> {
+        let apk = BundleFile::open(&args.apk_file).step(|| {
+            format!(
+                "Unable to open APK file '{}'",
+                args.apk_file.to_string_lossy()
+            )
+        })?;
+        let edit_id = self.open_edit(client, token, package_name).await?;
+        let result = self
+            .inner_upload_apk(client, token, package_name, &edit_id, args, &apk)
+            .await;
+
+        match result {
+            Ok(version_code) => {
+                output!(
+                    self.log,
+                    "{}",
+                    self.message(MessageId::CommitReleaseOfVersion, &[&version_code])
+                );
+                self.commit_edit(client, token, package_name, &edit_id)
+                    .await?;
+                self.output_console_links(package_name, Some(&args.track_name), None);
+            }
+            Err(_) => {
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+        }
+
+        result.map(|_| ())
+    }
+
+    async fn inner_upload_apk(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        args: &UploadApkArgs,
+        apk: &BundleFile,
+    ) -> Result<i32, Box<dyn Error>> {
+        let api = self.api(client, token);
+        let track = api
+            .get_track(package_name, edit_id, &args.track_name)
+            .await
+            .step(|| {
+                format!(
+                    "Unable to read track '{}' in edit '{}'",
+                    args.track_name, edit_id
+                )
+            })?;
+        output!(self.log, "Uploading APK ({} bytes)", apk.size());
+
+        let uploaded = ArtifactUploader::new(api, self.log, ArtifactKind::Apk)
+            .timeout(Duration::from_secs(args.timeout_secs))
+            .verify(Sha256Hash::default())
+            .upload::<Apk>(package_name, edit_id, apk)
+            .await
+            .step(|| format!("Unable to upload APK to edit '{}'", edit_id))?;
+
+        output!(
+            self.log,
+            "Uploaded version {} [{}]",
+            uploaded.version_code,
+            uploaded
+                .binary
+                .as_ref()
+                .and_then(|binary| binary.sha256.as_deref())
+                .unwrap_or("unknown")
+        );
+
+        let outcome = self
+            .update_release(
+                client,
+                token,
+                package_name,
+                edit_id,
+                &track,
+                uploaded.version_code,
+                args.release_name.as_deref(),
+                &[],
+                &self.config.track_defaults(&args.track_name),
+            )
+            .await?;
+
+        self.report_warnings(&outcome.warnings);
+
+        Ok(uploaded.version_code)
+    }
+
+    pub(crate) async fn list_apks(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let api = self.api(client, token);
+        let edit_id = api.insert_edit(package_name).await?.id;
+        let (apks_list, tracks_list) = tokio::try_join!(
+            api.list_apks(package_name, &edit_id),
+            api.list_tracks(package_name, &edit_id)
+        )?;
+
+        api.delete_edit(package_name, &edit_id).await?;
+
+        let version_code_tracks = Self::version_code_tracks(&tracks_list);
+        let sha256 = |apk: &Apk| {
+            apk.binary
+                .as_ref()
+                .and_then(|binary| binary.sha256.clone())
+                .unwrap_or_default()
+        };
+
+        if self.table.is_some() {
+            let mut table = Table::new(&["versionCode", "sha256", "tracks"]);
+
+            for apk in apks_list.apks.iter() {
+                table.add_row(vec![
+                    apk.version_code.to_string(),
+                    sha256(apk),
+                    version_code_tracks
+                        .get(&apk.version_code.to_string())
+                        .map(|track_names| track_names.join(","))
+                        .unwrap_or_default(),
+                ]);
+            }
+
+            return self.output_table(&table);
+        }
+
+        if apks_list.apks.is_empty() {
+            output!(self.log, "No APKs");
+        }
+
+        for apk in apks_list.apks.iter() {
+            let track_names = match version_code_tracks.get(&apk.version_code.to_string()) {
+                Some(track_names) => track_names
+                    .iter()
+                    .map(|name| format!("'{}'", name))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                None => "no tracks".to_string(),
+            };
+
+            output!(
+                self.log,
+                "Version {} [{}] ({})",
+                apk.version_code,
+                sha256(apk),
+                track_names
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,651 @@
+use crate::api::ArtifactKind;
+use crate::api_structs::{DeobfuscationFilesUploadResponse, LocalizedText};
+use crate::bundle_file::BundleFile;
+use crate::commands::track::parse_fraction;
+use crate::config::TrackDefaults;
+use crate::messages::MessageId;
+use crate::step::StepContext;
+use crate::table::Table;
+use crate::upload::ArtifactUploader;
+use crate::{output, throughput, upload, GplayTool, Outcome};
+use clap::{Args, Subcommand};
+use gcp_auth::Token;
+use reqwest::Client;
+use std::error::Error;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// Google Play's limit on the length of the release notes for each language
+const MAX_RELEASE_NOTES_CHARS: usize = 500;
+const DEFAULT_RELEASE_NOTES_LANG: &str = "en-US";
+
+#[derive(Subcommand)]
+pub enum BundlesCommands {
+    /// Lists uploaded bundle versions and the tracks that reference them
+    List,
+    /// Lists uploaded bundle versions not referenced by any track
+    Unused,
+    /// Upload a new bundle
+    Upload(UploadArgs),
+    /// Upload native debug symbols for a bundle that has already been uploaded
+    UploadSymbols(UploadSymbolsArgs),
+}
+
+#[derive(Args)]
+pub struct UploadSymbolsArgs {
+    /// The version code of the uploaded bundle
+    #[arg(long = "version-code", value_name = "VERSION")]
+    pub version_code: i32,
+    /// The native-debug-symbols.zip built along with the bundle
+    #[arg(short = 's', long = "symbols-file", value_name = "ZIP-FILE", value_hint = clap::ValueHint::FilePath)]
+    pub symbols_file: PathBuf,
+    /// The timeout for the upload in seconds
+    #[arg(
+        short = 't',
+        long = "timeout",
+        value_name = "TIMEOUT-SECS",
+        default_value = "300"
+    )]
+    pub timeout_secs: u64,
+}
+
+#[derive(Args)]
+pub struct UploadArgs {
+    /// The bundle file to upload, or '-' to read it from standard input
+    #[arg(short = 'b', long = "bundle-file", value_name = "AAB-FILE", value_hint = clap::ValueHint::FilePath)]
+    pub aab_file: PathBuf,
+    /// The ProGuard or R8 mapping.txt to attach to the bundle, for deobfuscating crash reports
+    #[arg(long = "mapping-file", value_name = "MAPPING-FILE", value_hint = clap::ValueHint::FilePath)]
+    pub mapping_file: Option<PathBuf>,
+    /// The name of the track to add the bundle too
+    #[arg(short = 'n', long = "track-name", value_name = "NAME")]
+    pub track_name: String,
+    /// The release name, which defaults to the bundle's version name and code
+    #[arg(long = "release-name", value_name = "NAME")]
+    pub release_name: Option<String>,
+    /// A text file of release notes, given once for each language
+    #[arg(long = "release-notes-file", value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+    pub release_notes_files: Vec<PathBuf>,
+    /// The language of the release notes file in the same position, e.g. en-US, which is the
+    /// default for a single file
+    #[arg(long = "release-notes-lang", value_name = "LANGUAGE")]
+    pub release_notes_langs: Vec<String>,
+    /// The status of the new release, instead of the track's default from the config file
+    #[arg(
+        long = "status",
+        value_name = "STATUS",
+        value_parser = ["draft", "inProgress", "halted", "completed"]
+    )]
+    pub status: Option<String>,
+    /// Roll the release out to this fraction of users, e.g. 0.05, making it inProgress
+    #[arg(long = "rollout-fraction", value_name = "FRACTION", value_parser = parse_fraction)]
+    pub rollout_fraction: Option<f64>,
+    /// The timeout for each upload request, i.e. for each chunk of a large bundle, in seconds
+    #[arg(
+        short = 't',
+        long = "timeout",
+        value_name = "TIMEOUT-SECS",
+        default_value = "300"
+    )]
+    pub timeout_secs: u64,
+    /// Upload bundles larger than this in chunks, resuming from the last chunk received if
+    /// the connection fails
+    #[arg(
+        long = "chunk-size",
+        value_name = "SIZE",
+        default_value = "8MiB",
+        value_parser = upload::parse_chunk_size
+    )]
+    pub chunk_size: u64,
+    /// Limit the upload to this many bytes per second, e.g. 10MBps or 512KB/s
+    #[arg(long = "max-upload-rate", value_name = "RATE", value_parser = throughput::parse_byte_rate)]
+    pub max_upload_rate: Option<u64>,
+    /// Roll back the edit if the whole upload, including the commit, takes longer than this
+    #[arg(long = "edit-timeout", value_name = "TIMEOUT-SECS")]
+    pub edit_timeout_secs: Option<u64>,
+    /// Allow uploading a lower version code than the track already has
+    #[arg(long = "allow-downgrade")]
+    pub allow_downgrade: bool,
+    /// Acknowledge that a large bundle, e.g. one with asset packs, may warn users on install
+    #[arg(long = "ack-install-warning")]
+    pub ack_install_warning: bool,
+    /// Release the version already uploaded if the bundle's version code has been used
+    #[arg(long = "assign-if-exists")]
+    pub assign_if_exists: bool,
+    /// Cut release notes down to the 500 characters Google Play accepts, instead of failing
+    #[arg(long = "truncate")]
+    pub truncate: bool,
+    /// Shell command to run after a successful upload, with GPLAY_* environment variables set
+    #[arg(long = "on-success", value_name = "CMD")]
+    pub on_success: Option<String>,
+    /// Shell command to run after a failed upload, with GPLAY_* environment variables set
+    #[arg(long = "on-failure", value_name = "CMD")]
+    pub on_failure: Option<String>,
+}
+
+// The track's defaults from the config file, overridden by the release options given with
+// the upload. The track's notes file is only kept when the upload has no release notes.
+pub(crate) fn release_settings(
+    mut track_defaults: TrackDefaults,
+    status: Option<&str>,
+    rollout_fraction: Option<f64>,
+    has_release_notes: bool,
+) -> Result<TrackDefaults, Box<dyn Error>> {
+    let is_staged = |status: Option<&str>| matches!(status, Some("inProgress" | "halted"));
+
+    if has_release_notes {
+        track_defaults.notes = None;
+    }
+
+    if let Some(status) = status {
+        if !is_staged(Some(status)) {
+            track_defaults.user_fraction = None;
+        }
+
+        track_defaults.status = Some(status.to_string());
+    }
+
+    if let Some(rollout_fraction) = rollout_fraction {
+        if status.is_none() {
+            track_defaults.status = Some("inProgress".to_string());
+        } else if !is_staged(track_defaults.status.as_deref()) {
+            return Err("--rollout-fraction needs a status of inProgress or halted".into());
+        }
+
+        track_defaults.user_fraction = Some(rollout_fraction);
+    }
+
+    if is_staged(track_defaults.status.as_deref()) && track_defaults.user_fraction.is_none() {
+        return Err(
+            "A staged rollout needs a fraction of users, given with --rollout-fraction".into(),
+        );
+    }
+
+    Ok(track_defaults)
+}
+
+/// Check that release notes are within what Google Play accepts
+pub(crate) fn check_release_notes(release_notes: &[LocalizedText]) -> Result<(), Box<dyn Error>> {
+    for release_note in release_notes.iter() {
+        let num_chars = release_note.text.chars().count();
+
+        if num_chars > MAX_RELEASE_NOTES_CHARS {
+            return Err(format!(
+                "Release notes for '{}' are {} characters, but may be at most {}",
+                release_note.language, num_chars, MAX_RELEASE_NOTES_CHARS
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that release notes are within what Google Play accepts or, with `truncate`, cut
+/// the ones that are too long down to the limit with a warning
+pub(crate) fn limit_release_notes(
+    mut release_notes: Vec<LocalizedText>,
+    truncate: bool,
+) -> Result<Outcome<Vec<LocalizedText>>, Box<dyn Error>> {
+    if !truncate {
+        check_release_notes(&release_notes)?;
+        return Ok(Outcome::new(release_notes));
+    }
+
+    let mut warnings = vec![];
+
+    for release_note in release_notes.iter_mut() {
+        if let Some((end, _)) = release_note
+            .text
+            .char_indices()
+            .nth(MAX_RELEASE_NOTES_CHARS)
+        {
+            warnings.push(format!(
+                "Release notes for '{}' were truncated from {} to {} characters",
+                release_note.language,
+                release_note.text.chars().count(),
+                MAX_RELEASE_NOTES_CHARS
+            ));
+            release_note.text.truncate(end);
+        }
+    }
+
+    Ok(Outcome {
+        value: release_notes,
+        warnings,
+    })
+}
+
+/// The release notes for an upload, which are read from the track's notes file if it has one
+pub(crate) fn release_notes_or_default(
+    release_notes: Vec<LocalizedText>,
+    track_defaults: &TrackDefaults,
+) -> Result<Vec<LocalizedText>, Box<dyn Error>> {
+    match &track_defaults.notes {
+        Some(notes_file) => read_release_notes(std::slice::from_ref(notes_file), &[]),
+        None => Ok(release_notes),
+    }
+}
+
+// Read release notes files, pairing each with the language given in the same position
+fn read_release_notes(
+    files: &[PathBuf],
+    languages: &[String],
+) -> Result<Vec<LocalizedText>, Box<dyn Error>> {
+    let default_languages = [DEFAULT_RELEASE_NOTES_LANG.to_string()];
+    let languages = match (files.len(), languages.len()) {
+        (1, 0) => &default_languages[..],
+        (num_files, num_languages) if num_files == num_languages => languages,
+        _ => {
+            return Err(
+                "Give a --release-notes-lang for each --release-notes-file, in the same order"
+                    .into(),
+            )
+        }
+    };
+    let mut release_notes = vec![];
+
+    for (file, language) in files.iter().zip(languages.iter()) {
+        let text = std::fs::read_to_string(file).step(|| {
+            format!(
+                "Unable to read release notes file '{}'",
+                file.to_string_lossy()
+            )
+        })?;
+
+        release_notes.push(LocalizedText {
+            language: language.clone(),
+            text: text.trim().to_string(),
+        });
+    }
+
+    Ok(release_notes)
+}
+
+impl GplayTool<'_> {
+    pub(crate) async fn upload_symbols(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        args: &UploadSymbolsArgs,
+    ) -> Result<(), Box<dyn Error>> {
+        let symbols = BundleFile::open(&args.symbols_file).step(|| {
+            format!(
+                "Unable to open symbols file '{}'",
+                args.symbols_file.to_string_lossy()
+            )
+        })?;
+        let edit_id = self.open_edit(client, token, package_name).await?;
+        let result = self
+            .inner_upload_symbols(client, token, package_name, &edit_id, args, &symbols)
+            .await;
+
+        match result {
+            Ok(()) => {
+                output!(self.log, "{}", self.message(MessageId::CommitSymbols, &[]));
+                self.commit_edit(client, token, package_name, &edit_id)
+                    .await?;
+            }
+            Err(_) => {
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+        }
+
+        result
+    }
+
+    async fn inner_upload_symbols(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        args: &UploadSymbolsArgs,
+        symbols: &BundleFile,
+    ) -> Result<(), Box<dyn Error>> {
+        output!(
+            self.log,
+            "Uploading native debug symbols for version {} ({} bytes)",
+            args.version_code,
+            symbols.size()
+        );
+
+        ArtifactUploader::new(
+            self.api(client, token),
+            self.log,
+            ArtifactKind::DeobfuscationFile {
+                version_code: args.version_code,
+                file_type: "nativeCode".to_string(),
+            },
+        )
+        .timeout(Duration::from_secs(args.timeout_secs))
+        .upload::<DeobfuscationFilesUploadResponse>(package_name, edit_id, symbols)
+        .await
+        .step(|| {
+            format!(
+                "Unable to upload native debug symbols for version {}",
+                args.version_code
+            )
+        })?;
+
+        Ok(())
+    }
+
+    pub(crate) async fn list_bundles(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let (edit_bundles_list, tracks_list) = self
+            .read_bundles_and_tracks(client, token, package_name)
+            .await?;
+        let version_code_tracks = Self::version_code_tracks(&tracks_list);
+
+        if self.table.is_some() {
+            let mut table = Table::new(&["versionCode", "sha256", "tracks"]);
+
+            for bundle in edit_bundles_list.bundles.iter() {
+                table.add_row(vec![
+                    bundle.version_code.to_string(),
+                    bundle.sha256.clone().unwrap_or_default(),
+                    version_code_tracks
+                        .get(&bundle.version_code.to_string())
+                        .map(|track_names| track_names.join(","))
+                        .unwrap_or_default(),
+                ]);
+            }
+
+            return self.output_table(&table);
+        }
+
+        for bundle in edit_bundles_list.bundles.iter() {
+            let track_names = match version_code_tracks.get(&bundle.version_code.to_string()) {
+                Some(track_names) => track_names
+                    .iter()
+                    .map(|name| format!("'{}'", name))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                None => "no tracks".to_string(),
+            };
+
+            output!(
+                self.log,
+                "Version {} [{}] ({})",
+                bundle.version_code,
+                bundle.sha256_or_unknown(),
+                track_names
+            );
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn unused_bundles(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let (edit_bundles_list, tracks_list) = self
+            .read_bundles_and_tracks(client, token, package_name)
+            .await?;
+        let version_code_tracks = Self::version_code_tracks(&tracks_list);
+
+        if self.table.is_some() {
+            let mut table = Table::new(&["versionCode", "sha256"]);
+
+            for bundle in edit_bundles_list.bundles.iter() {
+                if !version_code_tracks.contains_key(&bundle.version_code.to_string()) {
+                    table.add_row(vec![
+                        bundle.version_code.to_string(),
+                        bundle.sha256.clone().unwrap_or_default(),
+                    ]);
+                }
+            }
+
+            return self.output_table(&table);
+        }
+
+        let mut num_unused = 0;
+
+        for bundle in edit_bundles_list.bundles.iter() {
+            if !version_code_tracks.contains_key(&bundle.version_code.to_string()) {
+                output!(
+                    self.log,
+                    "Version {} [{}]",
+                    bundle.version_code,
+                    bundle.sha256_or_unknown()
+                );
+                num_unused += 1;
+            }
+        }
+
+        output!(
+            self.log,
+            "{} of {} bundles are not referenced by any track",
+            num_unused,
+            edit_bundles_list.bundles.len()
+        );
+
+        Ok(())
+    }
+
+    pub(crate) async fn upload(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        args: &UploadArgs,
+    ) -> Result<(), Box<dyn Error>> {
+        let release_notes =
+            read_release_notes(&args.release_notes_files, &args.release_notes_langs)?;
+        let track_defaults = release_settings(
+            self.config.track_defaults(&args.track_name),
+            args.status.as_deref(),
+            args.rollout_fraction,
+            !release_notes.is_empty(),
+        )?;
+        let release_notes = limit_release_notes(
+            release_notes_or_default(release_notes, &track_defaults)?,
+            args.truncate,
+        )?;
+        let result = self
+            .upload_bundle(
+                client,
+                token,
+                package_name,
+                &args.aab_file,
+                args.mapping_file.as_deref(),
+                &args.track_name,
+                args.release_name.as_deref(),
+                &release_notes.value,
+                &track_defaults,
+                args.timeout_secs,
+                args.chunk_size,
+                args.max_upload_rate,
+                args.edit_timeout_secs,
+                args.allow_downgrade,
+                args.ack_install_warning,
+                args.assign_if_exists,
+            )
+            .await;
+        let mut env = vec![
+            ("GPLAY_PACKAGE_NAME", package_name.to_string()),
+            ("GPLAY_TRACK", args.track_name.clone()),
+        ];
+
+        match result {
+            Ok(upload_outcome) => {
+                let mut outcome = release_notes.map(|_| ());
+                let upload_result = outcome.absorb(upload_outcome);
+
+                self.report_warnings(&outcome.warnings);
+                self.output_console_links(
+                    package_name,
+                    Some(&args.track_name),
+                    Some(upload_result.bundle.version_code),
+                );
+
+                if let Some(on_success) = &args.on_success {
+                    env.push(("GPLAY_EDIT_ID", upload_result.edit_id.clone()));
+                    env.push((
+                        "GPLAY_VERSION_CODE",
+                        upload_result.bundle.version_code.to_string(),
+                    ));
+                    env.push((
+                        "GPLAY_SHA256",
+                        upload_result.bundle.sha256.clone().unwrap_or_default(),
+                    ));
+                    self.run_hook(on_success, &env).await;
+                }
+
+                Ok(())
+            }
+            Err(err) => {
+                if let Some(on_failure) = &args.on_failure {
+                    env.push(("GPLAY_ERROR", err.to_string()));
+                    self.run_hook(on_failure, &env).await;
+                }
+
+                Err(err)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        upload: UploadArgs,
+    }
+
+    fn upload_args(args: &[&str]) -> UploadArgs {
+        TestCli::parse_from([&["gplay", "-b", "app.aab", "-n", "production"][..], args].concat())
+            .upload
+    }
+
+    fn args_settings(
+        track_defaults: TrackDefaults,
+        args: &UploadArgs,
+    ) -> Result<TrackDefaults, Box<dyn Error>> {
+        release_settings(
+            track_defaults,
+            args.status.as_deref(),
+            args.rollout_fraction,
+            !args.release_notes_files.is_empty(),
+        )
+    }
+
+    #[test]
+    fn test_args_settings() {
+        let config_defaults = TrackDefaults {
+            status: Some("inProgress".to_string()),
+            user_fraction: Some(0.05),
+            in_app_update_priority: None,
+            notes: Some(PathBuf::from("notes/production.txt")),
+        };
+
+        let settings = args_settings(
+            TrackDefaults::default(),
+            &upload_args(&["--rollout-fraction", "0.2"]),
+        )
+        .unwrap();
+
+        assert_eq!(settings.status.as_deref(), Some("inProgress"));
+        assert_eq!(settings.user_fraction, Some(0.2));
+
+        let settings = args_settings(
+            config_defaults.clone(),
+            &upload_args(&["--status", "completed"]),
+        )
+        .unwrap();
+
+        assert_eq!(settings.status.as_deref(), Some("completed"));
+        assert_eq!(settings.user_fraction, None);
+
+        assert!(args_settings(
+            TrackDefaults::default(),
+            &upload_args(&["--status", "draft", "--rollout-fraction", "0.2"])
+        )
+        .is_err());
+        assert!(args_settings(
+            TrackDefaults::default(),
+            &upload_args(&["--status", "inProgress"])
+        )
+        .is_err());
+        assert_eq!(
+            args_settings(config_defaults.clone(), &upload_args(&[]))
+                .unwrap()
+                .user_fraction,
+            Some(0.05)
+        );
+        assert_eq!(
+            args_settings(config_defaults.clone(), &upload_args(&[]))
+                .unwrap()
+                .notes,
+            config_defaults.notes
+        );
+        assert_eq!(
+            args_settings(
+                config_defaults,
+                &upload_args(&["--release-notes-file", "notes/hotfix.txt"])
+            )
+            .unwrap()
+            .notes,
+            None
+        );
+    }
+
+    #[test]
+    fn test_read_release_notes() {
+        let dir = std::env::temp_dir().join(format!("gplay-notes-{}", std::process::id()));
+        let en_file = dir.join("en.txt");
+        let de_file = dir.join("de.txt");
+
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&en_file, "Bug fixes\n").unwrap();
+        std::fs::write(&de_file, "Fehlerbehebungen").unwrap();
+
+        assert_eq!(
+            read_release_notes(&[en_file.clone()], &[]).unwrap(),
+            vec![LocalizedText {
+                language: "en-US".to_string(),
+                text: "Bug fixes".to_string(),
+            }]
+        );
+        assert_eq!(
+            read_release_notes(
+                &[en_file.clone(), de_file.clone()],
+                &["en-GB".to_string(), "de-DE".to_string()]
+            )
+            .unwrap()[1]
+                .language,
+            "de-DE"
+        );
+        assert!(read_release_notes(&[en_file, de_file.clone()], &[]).is_err());
+
+        let read_de = || read_release_notes(&[de_file.clone()], &["de-DE".to_string()]).unwrap();
+
+        std::fs::write(&de_file, "ä".repeat(MAX_RELEASE_NOTES_CHARS)).unwrap();
+        assert!(limit_release_notes(read_de(), false).is_ok());
+        std::fs::write(&de_file, "ä".repeat(MAX_RELEASE_NOTES_CHARS + 1)).unwrap();
+        assert!(limit_release_notes(read_de(), false).is_err());
+
+        // Truncating cuts on a character boundary, not a byte one
+        let truncated = limit_release_notes(read_de(), true).unwrap();
+
+        assert_eq!(truncated.value[0].text, "ä".repeat(MAX_RELEASE_NOTES_CHARS));
+        assert_eq!(truncated.warnings.len(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
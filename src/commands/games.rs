@@ -0,0 +1,166 @@
+use crate::api_structs::*;
+use crate::table::Table;
+use crate::{output, GplayTool};
+use clap::{Args, Subcommand};
+use gcp_auth::Token;
+use reqwest::Client;
+use std::error::Error;
+
+#[derive(Subcommand)]
+pub enum GamesCommands {
+    /// List the achievements configured for a game
+    Achievements(GamesArgs),
+    /// List the leaderboards configured for a game
+    Leaderboards(GamesArgs),
+}
+
+#[derive(Args)]
+pub struct GamesArgs {
+    /// The Play Games Services application ID, which is not the package name
+    #[arg(long = "application-id", value_name = "ID")]
+    pub application_id: String,
+}
+
+// The draft name of an achievement or leaderboard, in the first language it has one for
+fn draft_name(draft: Option<&GamesConfigurationDetail>) -> String {
+    draft
+        .and_then(|draft| draft.name.as_ref())
+        .and_then(|name| name.translations.first())
+        .map(|translation| translation.value.clone())
+        .unwrap_or_default()
+}
+
+impl GplayTool<'_> {
+    pub(crate) async fn run_games(
+        &self,
+        client: &Client,
+        token: &Token,
+        command: &GamesCommands,
+    ) -> Result<(), Box<dyn Error>> {
+        match command {
+            GamesCommands::Achievements(args) => {
+                self.list_achievements(client, token, &args.application_id)
+                    .await
+            }
+            GamesCommands::Leaderboards(args) => {
+                self.list_leaderboards(client, token, &args.application_id)
+                    .await
+            }
+        }
+    }
+
+    async fn list_achievements(
+        &self,
+        client: &Client,
+        token: &Token,
+        application_id: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let api = self.api(client, token);
+        let mut achievements = vec![];
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let list = api
+                .list_achievements(application_id, page_token.as_deref())
+                .await?;
+
+            achievements.extend(list.items);
+            page_token = list.next_page_token;
+
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        if self.table.is_some() {
+            let mut table = Table::new(&["id", "type", "initialState", "name"]);
+
+            for achievement in achievements.iter() {
+                table.add_row(vec![
+                    achievement.id.clone(),
+                    achievement.achievement_type.clone().unwrap_or_default(),
+                    achievement.initial_state.clone().unwrap_or_default(),
+                    draft_name(achievement.draft.as_ref()),
+                ]);
+            }
+
+            return self.output_table(&table);
+        }
+
+        if achievements.is_empty() {
+            output!(self.log, "No achievements");
+        }
+
+        for achievement in achievements.iter() {
+            output!(
+                self.log,
+                "Achievement '{}' {} ({})",
+                achievement.id,
+                draft_name(achievement.draft.as_ref()),
+                achievement
+                    .achievement_type
+                    .as_deref()
+                    .unwrap_or("unknown type")
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn list_leaderboards(
+        &self,
+        client: &Client,
+        token: &Token,
+        application_id: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let api = self.api(client, token);
+        let mut leaderboards = vec![];
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let list = api
+                .list_leaderboards(application_id, page_token.as_deref())
+                .await?;
+
+            leaderboards.extend(list.items);
+            page_token = list.next_page_token;
+
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        if self.table.is_some() {
+            let mut table = Table::new(&["id", "scoreOrder", "name"]);
+
+            for leaderboard in leaderboards.iter() {
+                table.add_row(vec![
+                    leaderboard.id.clone(),
+                    leaderboard.score_order.clone().unwrap_or_default(),
+                    draft_name(leaderboard.draft.as_ref()),
+                ]);
+            }
+
+            return self.output_table(&table);
+        }
+
+        if leaderboards.is_empty() {
+            output!(self.log, "No leaderboards");
+        }
+
+        for leaderboard in leaderboards.iter() {
+            output!(
+                self.log,
+                "Leaderboard '{}' {} ({})",
+                leaderboard.id,
+                draft_name(leaderboard.draft.as_ref()),
+                leaderboard
+                    .score_order
+                    .as_deref()
+                    .unwrap_or("unknown order")
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,8 @@
+pub mod apks;
+pub mod bundles;
+#[cfg(feature = "games")]
+pub mod games;
+pub mod reviews;
+pub mod schema;
+pub mod token;
+pub mod track;
@@ -0,0 +1,205 @@
+use crate::api_structs::*;
+use crate::{error, output, GplayTool};
+use clap::{Args, Subcommand};
+use easy_error::ResultExt;
+use gcp_auth::Token;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Subcommand)]
+pub enum ReviewsCommands {
+    /// Show rating statistics for the reviews of the last week
+    Stats,
+    /// Reply to reviews from a CSV file with reviewId and text columns
+    ReplyBatch(ReplyBatchArgs),
+}
+
+#[derive(Args)]
+pub struct ReplyBatchArgs {
+    /// The CSV file of replies
+    #[arg(short = 'f', long = "file", value_name = "CSV-FILE", value_hint = clap::ValueHint::FilePath)]
+    pub replies_file: PathBuf,
+}
+
+impl GplayTool<'_> {
+    // Pace replies so that a large batch doesn't hit the API rate limit
+    const REPLY_INTERVAL: Duration = Duration::from_millis(500);
+
+    // The API only returns reviews from the last week, a page at a time
+    async fn get_reviews(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+    ) -> Result<Vec<Review>, Box<dyn Error>> {
+        let mut reviews = vec![];
+        let mut page_token: Option<String> = None;
+
+        loop {
+            let reviews_list = self
+                .api(client, token)
+                .list_reviews(package_name, page_token.as_deref())
+                .await?;
+
+            reviews.extend(reviews_list.reviews);
+            page_token = reviews_list
+                .token_pagination
+                .and_then(|pagination| pagination.next_page_token);
+
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        Ok(reviews)
+    }
+
+    pub(crate) async fn review_stats(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let reviews = self.get_reviews(client, token, package_name).await?;
+        let user_comments: Vec<&UserComment> = reviews
+            .iter()
+            .filter_map(|review| review.user_comment())
+            .collect();
+
+        if user_comments.is_empty() {
+            output!(self.log, "No reviews in the last week");
+            return Ok(());
+        }
+
+        let num_reviews = user_comments.len();
+        let mut ratings = [0usize; 5];
+        let mut languages: BTreeMap<&str, usize> = BTreeMap::new();
+        // Version -> (number of reviews, total stars)
+        let mut versions: BTreeMap<String, (usize, i32)> = BTreeMap::new();
+
+        for user_comment in user_comments.iter() {
+            if (1..=5).contains(&user_comment.star_rating) {
+                ratings[user_comment.star_rating as usize - 1] += 1;
+            }
+
+            *languages
+                .entry(
+                    user_comment
+                        .reviewer_language
+                        .as_deref()
+                        .unwrap_or("unknown"),
+                )
+                .or_default() += 1;
+
+            let version = match (
+                &user_comment.app_version_name,
+                user_comment.app_version_code,
+            ) {
+                (Some(name), Some(code)) => format!("{} ({})", name, code),
+                (None, Some(code)) => code.to_string(),
+                _ => "unknown".to_string(),
+            };
+            let entry = versions.entry(version).or_default();
+
+            entry.0 += 1;
+            entry.1 += user_comment.star_rating;
+        }
+
+        let total_stars: i32 = user_comments.iter().map(|c| c.star_rating).sum();
+
+        output!(
+            self.log,
+            "{} reviews, average rating {:.2}",
+            num_reviews,
+            total_stars as f64 / num_reviews as f64
+        );
+
+        for (index, count) in ratings.iter().enumerate().rev() {
+            output!(
+                self.log,
+                "  {} stars: {} ({:.1}%)",
+                index + 1,
+                count,
+                *count as f64 * 100.0 / num_reviews as f64
+            );
+        }
+
+        output!(self.log, "By language:");
+
+        for (language, count) in languages.iter() {
+            output!(self.log, "  {}: {}", language, count);
+        }
+
+        output!(self.log, "By version:");
+
+        for (version, (count, stars)) in versions.iter() {
+            output!(
+                self.log,
+                "  {}: {}, average rating {:.2}",
+                version,
+                count,
+                *stars as f64 / *count as f64
+            );
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn reply_reviews(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        replies_file: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        #[derive(Deserialize)]
+        struct ReplyRow {
+            #[serde(rename = "reviewId")]
+            review_id: String,
+            text: String,
+        }
+
+        let mut reader =
+            csv::Reader::from_path(replies_file).context("Unable to read replies file")?;
+        let rows = reader
+            .deserialize::<ReplyRow>()
+            .collect::<Result<Vec<ReplyRow>, csv::Error>>()
+            .context("Invalid replies file")?;
+        let mut num_failed = 0;
+
+        for (index, row) in rows.iter().enumerate() {
+            if index > 0 {
+                tokio::time::sleep(Self::REPLY_INTERVAL).await;
+            }
+
+            match self
+                .api(client, token)
+                .reply_review(package_name, &row.review_id, &row.text)
+                .await
+            {
+                Ok(_) => output!(self.log, "Replied to review '{}'", row.review_id),
+                Err(err) => {
+                    // Rows are numbered as in a spreadsheet, after the header row
+                    error!(
+                        self.log,
+                        "Row {}: unable to reply to review '{}': {}",
+                        index + 2,
+                        row.review_id,
+                        crate::error::describe(&err)
+                    );
+                    num_failed += 1;
+                }
+            }
+        }
+
+        if num_failed > 0 {
+            return Err(format!("{} of {} replies failed", num_failed, rows.len()).into());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{output, GplayTool};
+use clap::{Args, Subcommand, ValueEnum};
+
+const TRACKS_SCHEMA: &str = include_str!("../../schemas/tracks.schema.json");
+const CONFIG_SCHEMA: &str = include_str!("../../schemas/config.schema.json");
+
+#[derive(Subcommand)]
+pub enum SchemaCommands {
+    /// Print the JSON Schema for one of the files that gplay reads
+    Print(SchemaPrintArgs),
+}
+
+#[derive(Args)]
+pub struct SchemaPrintArgs {
+    /// The file format to print the schema for
+    #[arg(value_enum, default_value = "tracks")]
+    pub format: SchemaFormat,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SchemaFormat {
+    /// The YAML file used by 'track apply'
+    Tracks,
+    /// The gplay.toml config file
+    Config,
+}
+
+impl GplayTool<'_> {
+    pub(crate) fn print_schema(&self, format: SchemaFormat) {
+        let schema = match format {
+            SchemaFormat::Tracks => TRACKS_SCHEMA,
+            SchemaFormat::Config => CONFIG_SCHEMA,
+        };
+
+        output!(self.log, "{}", schema.trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schemas_are_json() {
+        for schema in [TRACKS_SCHEMA, CONFIG_SCHEMA] {
+            let value: serde_json::Value = serde_json::from_str(schema).unwrap();
+
+            assert!(value.get("$schema").is_some());
+        }
+    }
+}
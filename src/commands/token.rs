@@ -0,0 +1,50 @@
+use crate::credentials::{CredentialsProvider, FileCredentials};
+use crate::{output, GplayTool};
+use clap::{Args, Subcommand};
+use std::error::Error;
+
+#[derive(Subcommand)]
+pub enum TokenCommands {
+    /// Print a fresh OAuth access token, e.g. for an ad-hoc curl call
+    Print(TokenPrintArgs),
+}
+
+#[derive(Args)]
+pub struct TokenPrintArgs {
+    /// Scopes for the token instead of the Android Publisher scope, comma separated
+    #[arg(long = "scopes", value_name = "SCOPES", value_delimiter = ',')]
+    pub scopes: Vec<String>,
+}
+
+impl GplayTool<'_> {
+    pub(crate) async fn print_token(
+        &self,
+        package_name: Option<&str>,
+        default_credentials: Option<&dyn CredentialsProvider>,
+        scopes: &[String],
+    ) -> Result<(), Box<dyn Error>> {
+        let package_credentials = package_name
+            .and_then(|package_name| self.config.credentials_file(package_name))
+            .map(FileCredentials::new);
+        let credentials: &dyn CredentialsProvider =
+            match (&package_credentials, default_credentials) {
+                (Some(package_credentials), _) => package_credentials,
+                (None, Some(default_credentials)) => default_credentials,
+                (None, None) => {
+                    return Err("A credentials file must be given with --cred-file".into())
+                }
+            };
+        let scopes: Vec<&str> = if scopes.is_empty() {
+            vec![Self::ANDROID_PUBLISHER_SCOPE]
+        } else {
+            scopes.iter().map(|scope| scope.as_str()).collect()
+        };
+
+        // Nothing else is output so that the token can be captured by the shell
+        let token = self.authenticate(credentials, &scopes).await?;
+
+        output!(self.log, "{}", token.as_str());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,924 @@
+use crate::api_structs::*;
+use crate::messages::MessageId;
+use crate::table::Table;
+use crate::{output, tracks_file, GplayTool};
+use clap::{Args, Subcommand};
+use easy_error::ResultExt;
+use gcp_auth::Token;
+use reqwest::Client;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+#[derive(Subcommand)]
+pub enum TrackCommands {
+    /// List available release tracks
+    List,
+    /// Show the releases of a track
+    Describe(DescribeTrackArgs),
+    /// Show how the releases of one track differ from another's
+    Diff(DiffTracksArgs),
+    /// Reconcile the live tracks with the desired state declared in a YAML file
+    Apply(ApplyTracksArgs),
+    /// Roll out the track's staged release to all users
+    Complete(CompleteReleaseArgs),
+    /// Add or remove countries targeted by a release, leaving the rest of it unchanged
+    Countries(CountriesArgs),
+    /// Change the fraction of users that the track's staged release is rolled out to
+    SetRollout(SetRolloutArgs),
+    /// Release a version that has already been uploaded to a track
+    Assign(AssignArgs),
+}
+
+#[derive(Args)]
+pub struct DescribeTrackArgs {
+    /// The name of the track to describe
+    #[arg(short = 'n', long = "track-name", value_name = "NAME")]
+    pub track_name: String,
+    /// Only show releases with this status
+    #[arg(
+        long = "release-status",
+        value_name = "STATUS",
+        value_parser = ["inProgress", "halted", "draft", "completed"]
+    )]
+    pub release_status: Option<String>,
+}
+
+#[derive(Args)]
+pub struct DiffTracksArgs {
+    /// The track that would be promoted
+    #[arg(long = "from", value_name = "NAME")]
+    pub from: String,
+    /// The track that would be promoted to
+    #[arg(long = "to", value_name = "NAME")]
+    pub to: String,
+}
+
+#[derive(Args)]
+pub struct CompleteReleaseArgs {
+    /// The name of the track with the inProgress release
+    #[arg(
+        short = 'n',
+        long = "track-name",
+        visible_alias = "track",
+        value_name = "NAME"
+    )]
+    pub track_name: String,
+    /// Rename the release, e.g. to "2.4.1 hotfix"
+    #[arg(long = "release-name", value_name = "NAME")]
+    pub release_name: Option<String>,
+}
+
+#[derive(Args)]
+pub struct SetRolloutArgs {
+    /// The name of the track with the inProgress or halted release
+    #[arg(short = 'n', long = "track-name", value_name = "NAME")]
+    pub track_name: String,
+    /// The fraction of users to roll the release out to, e.g. 0.2
+    #[arg(long = "fraction", value_name = "FRACTION", value_parser = parse_fraction)]
+    pub fraction: f64,
+    /// Rename the release, e.g. to "2.4.1 hotfix"
+    #[arg(long = "release-name", value_name = "NAME")]
+    pub release_name: Option<String>,
+}
+
+#[derive(Args)]
+pub struct AssignArgs {
+    /// The version code of the uploaded bundle
+    #[arg(long = "version-code", value_name = "VERSION")]
+    pub version_code: i32,
+    /// The name of the track to release the version to
+    #[arg(
+        short = 'n',
+        long = "track-name",
+        visible_alias = "track",
+        value_name = "NAME"
+    )]
+    pub track_name: String,
+    /// The name of the release, instead of the version code
+    #[arg(short = 'r', long = "release-name", value_name = "NAME")]
+    pub release_name: Option<String>,
+}
+
+/// Parse a fraction of users for a staged rollout, which must be more than 0 and less than 1
+pub fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(fraction) if fraction > 0.0 && fraction < 1.0 => Ok(fraction),
+        _ => Err(format!(
+            "'{}' is not a fraction between 0 and 1, use the complete command to roll out to all users",
+            s
+        )),
+    }
+}
+
+#[derive(Args)]
+pub struct CountriesArgs {
+    /// The name of the track with the release
+    #[arg(short = 'n', long = "track-name", value_name = "NAME")]
+    pub track_name: String,
+    /// Change the most current release with this status instead of the most current release
+    #[arg(
+        long = "release-status",
+        value_name = "STATUS",
+        value_parser = ["inProgress", "halted", "draft", "completed"]
+    )]
+    pub release_status: Option<String>,
+    /// Countries to start targeting, as ISO 3166-1 alpha-2 codes
+    #[arg(long = "add", value_name = "COUNTRIES", value_delimiter = ',', value_parser = parse_country)]
+    pub add: Vec<String>,
+    /// Countries to stop targeting
+    #[arg(long = "remove", value_name = "COUNTRIES", value_delimiter = ',', value_parser = parse_country)]
+    pub remove: Vec<String>,
+    /// Target only the added countries, replacing any the release already targets. Needed to
+    /// restrict a release that is available in every country.
+    #[arg(long = "only", requires = "add")]
+    pub only: bool,
+}
+
+fn parse_country(s: &str) -> Result<String, String> {
+    if s.len() == 2 && s.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(s.to_ascii_uppercase())
+    } else {
+        Err(format!("'{}' is not a two letter country code", s))
+    }
+}
+
+// A fraction of users as a percentage, e.g. 0.29 as `29%` rather than `28.999999999999996%`
+fn format_percentage(fraction: f64) -> String {
+    let percentage = format!("{:.1}", fraction * 100.0);
+
+    format!("{}%", percentage.trim_end_matches(".0"))
+}
+
+// The release's country targeting after adding and removing countries, keeping the order of
+// the countries it already had. A release without country targeting is available in every
+// country, so is only restricted to the added countries when asked for with `only`.
+fn update_countries(
+    country_targeting: Option<&CountryTargeting>,
+    add: &[String],
+    remove: &[String],
+    only: bool,
+) -> Result<CountryTargeting, Box<dyn Error>> {
+    let mut country_targeting = match country_targeting {
+        _ if only => CountryTargeting {
+            countries: vec![],
+            include_rest_of_world: false,
+        },
+        Some(country_targeting) => country_targeting.clone(),
+        None => {
+            return Err(
+                "The release is available in every country, use --only to restrict it to the added countries"
+                    .into(),
+            )
+        }
+    };
+
+    for country in add.iter() {
+        if !country_targeting.countries.contains(country) {
+            country_targeting.countries.push(country.clone());
+        }
+    }
+
+    country_targeting
+        .countries
+        .retain(|country| !remove.contains(country));
+
+    if country_targeting.countries.is_empty() && !country_targeting.include_rest_of_world {
+        return Err("A release must target at least one country".into());
+    }
+
+    Ok(country_targeting)
+}
+
+#[derive(Args)]
+pub struct ApplyTracksArgs {
+    /// The YAML file declaring the desired releases of each track
+    #[arg(short = 'f', long = "file", value_name = "YAML-FILE", value_hint = clap::ValueHint::FilePath)]
+    pub tracks_file: PathBuf,
+    /// Only show the changes that would be made
+    #[arg(long = "dry-run")]
+    pub dry_run: bool,
+}
+
+impl GplayTool<'_> {
+    pub(crate) async fn list_tracks(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let tracks_list = self.read_tracks(client, token, package_name).await?;
+
+        if self.table.is_some() {
+            let mut table = Table::new(&["track", "releases"]);
+
+            for track in tracks_list.tracks.iter() {
+                table.add_row(vec![track.name.clone(), track.releases.len().to_string()]);
+            }
+
+            return self.output_table(&table);
+        }
+
+        for track in tracks_list.tracks.iter() {
+            output!(self.log, "Track '{}'", track.name);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) async fn describe_track(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        track_name: &str,
+        release_status: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let tracks_list = self.read_tracks(client, token, package_name).await?;
+        let track = tracks_list
+            .tracks
+            .iter()
+            .find(|track| track.name == track_name)
+            .ok_or_else(|| format!("Track '{}' does not exist", track_name))?;
+
+        let releases: Vec<&Release> = track
+            .releases_by_precedence()
+            .into_iter()
+            .filter(|release| release_status.map_or(true, |status| release.status == status))
+            .collect();
+
+        output!(self.log, "Track '{}'", track.name);
+
+        if releases.is_empty() {
+            output!(self.log, "  no releases");
+        }
+
+        for release in releases {
+            output!(self.log, "  {}", Self::describe_release(release));
+
+            if let Some(priority) = release.in_app_update_priority {
+                output!(self.log, "    in-app update priority {}", priority);
+            }
+
+            if let Some(country_targeting) = &release.country_targeting {
+                output!(
+                    self.log,
+                    "    countries {}{}",
+                    country_targeting.countries.join(", "),
+                    if country_targeting.include_rest_of_world {
+                        " and rest of world"
+                    } else {
+                        ""
+                    }
+                );
+            }
+
+            for release_note in release.release_notes.iter().flatten() {
+                output!(
+                    self.log,
+                    "    notes ({}): {}",
+                    release_note.language,
+                    release_note.text
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    // Releases only on the 'to' track are shown as removed and those only on the 'from' track
+    // as added, which is what promoting 'from' to 'to' would do
+    pub(crate) async fn diff_tracks(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        from_track: &str,
+        to_track: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let tracks_list = self.read_tracks(client, token, package_name).await?;
+        let find_releases = |track_name: &str| {
+            tracks_list
+                .tracks
+                .iter()
+                .find(|track| track.name == track_name)
+                .map(|track| track.releases.as_slice())
+                .ok_or_else(|| format!("Track '{}' does not exist", track_name))
+        };
+        let from_releases = find_releases(from_track)?;
+        let to_releases = find_releases(to_track)?;
+
+        if from_releases == to_releases {
+            output!(self.log, "No differences");
+            return Ok(());
+        }
+
+        output!(self.log, "~ Track '{}' -> '{}'", from_track, to_track);
+
+        for (prefix, releases, other_releases) in [
+            ("-", to_releases, from_releases),
+            ("+", from_releases, to_releases),
+        ] {
+            for release in releases
+                .iter()
+                .filter(|release| !other_releases.contains(release))
+            {
+                output!(self.log, "  {} {}", prefix, Self::describe_release(release));
+
+                for release_note in release.release_notes.iter().flatten() {
+                    output!(
+                        self.log,
+                        "      notes ({}): {}",
+                        release_note.language,
+                        release_note.text
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn describe_release(release: &Release) -> String {
+        let mut description = format!(
+            "{} [{}]",
+            release.status,
+            release
+                .version_codes
+                .as_ref()
+                .map(|version_codes| version_codes.join(", "))
+                .unwrap_or_default()
+        );
+
+        if let Some(name) = &release.name {
+            description.push_str(&format!(" '{}'", name));
+        }
+
+        if let Some(user_fraction) = release.user_fraction {
+            description.push_str(&format!(" {}", format_percentage(user_fraction)));
+        }
+
+        description
+    }
+
+    pub(crate) async fn apply_tracks(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        tracks_file: &Path,
+        dry_run: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let desired = tracks_file::parse(
+            &std::fs::read_to_string(tracks_file).context("Unable to read tracks file")?,
+        )
+        .map_err(|errors| {
+            format!(
+                "Invalid tracks file '{}':\n  {}",
+                tracks_file.to_string_lossy(),
+                errors.join("\n  ")
+            )
+        })?;
+        let edit_id = self.open_edit(client, token, package_name).await?;
+        let result = self
+            .inner_apply_tracks(client, token, package_name, &edit_id, &desired, dry_run)
+            .await;
+
+        match result {
+            Ok(num_changed) if num_changed > 0 && !dry_run => {
+                output!(
+                    self.log,
+                    "{}",
+                    self.message(MessageId::CommitTrackChanges, &[&num_changed])
+                );
+                self.commit_edit(client, token, package_name, &edit_id)
+                    .await?;
+                self.output_console_links(package_name, None, None);
+            }
+            Ok(num_changed) => {
+                if num_changed == 0 {
+                    output!(
+                        self.log,
+                        "{}",
+                        self.message(MessageId::NoChangesTracks, &[])
+                    );
+                }
+
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+            Err(_) => {
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+        }
+
+        result.map(|_| ())
+    }
+
+    async fn inner_apply_tracks(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        desired: &TracksList,
+        dry_run: bool,
+    ) -> Result<usize, Box<dyn Error>> {
+        let live = self
+            .api(client, token)
+            .list_tracks(package_name, edit_id)
+            .await?;
+        let mut num_changed = 0;
+
+        for desired_track in desired.tracks.iter() {
+            let live_releases = live
+                .tracks
+                .iter()
+                .find(|track| track.name == desired_track.name)
+                .map(|track| track.releases.as_slice())
+                .unwrap_or_default();
+
+            if live_releases == desired_track.releases.as_slice() {
+                continue;
+            }
+
+            output!(self.log, "~ Track '{}'", desired_track.name);
+
+            for release in live_releases
+                .iter()
+                .filter(|release| !desired_track.releases.contains(release))
+            {
+                output!(self.log, "  - {}", Self::describe_release(release));
+            }
+
+            for release in desired_track
+                .releases
+                .iter()
+                .filter(|release| !live_releases.contains(release))
+            {
+                output!(self.log, "  + {}", Self::describe_release(release));
+            }
+
+            if !dry_run {
+                self.api(client, token)
+                    .update_track(package_name, edit_id, desired_track)
+                    .await?;
+            }
+
+            num_changed += 1;
+        }
+
+        Ok(num_changed)
+    }
+
+    pub(crate) async fn complete_release(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        track_name: &str,
+        release_name: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let edit_id = self.open_edit(client, token, package_name).await?;
+        let result = self
+            .inner_complete_release(
+                client,
+                token,
+                package_name,
+                &edit_id,
+                track_name,
+                release_name,
+            )
+            .await;
+
+        match result {
+            Ok(()) => {
+                output!(
+                    self.log,
+                    "{}",
+                    self.message(MessageId::CommitCompletedRelease, &[])
+                );
+                self.commit_edit(client, token, package_name, &edit_id)
+                    .await?;
+                self.output_console_links(package_name, Some(track_name), None);
+            }
+            Err(_) => {
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+        }
+
+        result
+    }
+
+    async fn inner_complete_release(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        track_name: &str,
+        release_name: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let api = self.api(client, token);
+        let track = api.get_track(package_name, edit_id, track_name).await?;
+        let release = track.select_release(Some("inProgress")).ok_or_else(|| {
+            format!(
+                "Track '{}' has no inProgress release to complete",
+                track_name
+            )
+        })?;
+
+        output!(self.log, "Completing {}", Self::describe_release(release));
+
+        // The completed release replaces the one that it was being rolled out over
+        let mut releases: Vec<Release> = track
+            .releases
+            .iter()
+            .filter(|other| other.status != "completed" && *other != release)
+            .cloned()
+            .collect();
+
+        releases.insert(
+            0,
+            Release {
+                name: release_name
+                    .map(|release_name| release_name.to_string())
+                    .or_else(|| release.name.clone()),
+                status: "completed".to_string(),
+                user_fraction: None,
+                ..release.clone()
+            },
+        );
+        api.update_track(package_name, edit_id, &Track::new(track_name, releases))
+            .await?;
+
+        Ok(())
+    }
+
+    pub(crate) async fn update_release_countries(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        args: &CountriesArgs,
+    ) -> Result<(), Box<dyn Error>> {
+        if args.add.is_empty() && args.remove.is_empty() {
+            return Err("Countries must be given with --add or --remove".into());
+        }
+
+        let edit_id = self.open_edit(client, token, package_name).await?;
+        let result = self
+            .inner_update_release_countries(client, token, package_name, &edit_id, args)
+            .await;
+
+        match result {
+            Ok(true) => {
+                output!(
+                    self.log,
+                    "{}",
+                    self.message(MessageId::CommitCountryTargeting, &[])
+                );
+                self.commit_edit(client, token, package_name, &edit_id)
+                    .await?;
+                self.output_console_links(package_name, Some(&args.track_name), None);
+            }
+            Ok(false) => {
+                output!(
+                    self.log,
+                    "{}",
+                    self.message(MessageId::NoChangesCountries, &[])
+                );
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+            Err(_) => {
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+        }
+
+        result.map(|_| ())
+    }
+
+    async fn inner_update_release_countries(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        args: &CountriesArgs,
+    ) -> Result<bool, Box<dyn Error>> {
+        let api = self.api(client, token);
+        let mut track = api
+            .get_track(package_name, edit_id, &args.track_name)
+            .await?;
+        let selected = track
+            .select_release(args.release_status.as_deref())
+            .ok_or_else(|| format!("Track '{}' has no matching release", args.track_name))?
+            .clone();
+        let country_targeting = update_countries(
+            selected.country_targeting.as_ref(),
+            &args.add,
+            &args.remove,
+            args.only,
+        )?;
+
+        if selected.country_targeting.as_ref() == Some(&country_targeting) {
+            return Ok(false);
+        }
+
+        output!(
+            self.log,
+            "Targeting {} with {}{}",
+            Self::describe_release(&selected),
+            country_targeting.countries.join(", "),
+            if country_targeting.include_rest_of_world {
+                " and rest of world"
+            } else {
+                ""
+            }
+        );
+
+        for release in track.releases.iter_mut() {
+            if *release == selected {
+                release.country_targeting = Some(country_targeting.clone());
+            }
+        }
+
+        api.update_track(package_name, edit_id, &track).await?;
+
+        Ok(true)
+    }
+
+    pub(crate) async fn set_rollout(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        track_name: &str,
+        fraction: f64,
+        release_name: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let edit_id = self.open_edit(client, token, package_name).await?;
+        let result = self
+            .inner_set_rollout(
+                client,
+                token,
+                package_name,
+                &edit_id,
+                track_name,
+                fraction,
+                release_name,
+            )
+            .await;
+
+        match result {
+            Ok(true) => {
+                output!(self.log, "{}", self.message(MessageId::CommitRollout, &[]));
+                self.commit_edit(client, token, package_name, &edit_id)
+                    .await?;
+                self.output_console_links(package_name, Some(track_name), None);
+            }
+            Ok(false) => {
+                output!(
+                    self.log,
+                    "{}",
+                    self.message(MessageId::NoChangesRollout, &[])
+                );
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+            Err(_) => {
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+        }
+
+        result.map(|_| ())
+    }
+
+    async fn inner_set_rollout(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        track_name: &str,
+        fraction: f64,
+        release_name: Option<&str>,
+    ) -> Result<bool, Box<dyn Error>> {
+        let api = self.api(client, token);
+        let mut track = api.get_track(package_name, edit_id, track_name).await?;
+        let live_track = track.clone();
+        let selected = track
+            .releases_by_precedence()
+            .into_iter()
+            .find(|release| release.status == "inProgress" || release.status == "halted")
+            .cloned()
+            .ok_or_else(|| format!("Track '{}' has no staged release", track_name))?;
+
+        output!(
+            self.log,
+            "Rolling out {} to {}",
+            Self::describe_release(&selected),
+            format_percentage(fraction)
+        );
+
+        // Setting the fraction of a halted release resumes it
+        for release in track.releases.iter_mut() {
+            if *release == selected {
+                release.status = "inProgress".to_string();
+                release.user_fraction = Some(fraction);
+
+                if let Some(release_name) = release_name {
+                    release.name = Some(release_name.to_string());
+                }
+            }
+        }
+
+        if track == live_track {
+            return Ok(false);
+        }
+
+        api.update_track(package_name, edit_id, &track).await?;
+
+        Ok(true)
+    }
+
+    pub(crate) async fn assign_version(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        args: &AssignArgs,
+    ) -> Result<(), Box<dyn Error>> {
+        let edit_id = self.open_edit(client, token, package_name).await?;
+        let result = self
+            .inner_assign_version(client, token, package_name, &edit_id, args)
+            .await;
+
+        match result {
+            Ok(true) => {
+                output!(self.log, "{}", self.message(MessageId::CommitRelease, &[]));
+                self.commit_edit(client, token, package_name, &edit_id)
+                    .await?;
+                self.output_console_links(
+                    package_name,
+                    Some(&args.track_name),
+                    Some(args.version_code),
+                );
+            }
+            Ok(false) => {
+                output!(
+                    self.log,
+                    "{}",
+                    self.message(MessageId::NoChangesRelease, &[&args.track_name])
+                );
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+            Err(_) => {
+                self.api(client, token)
+                    .delete_edit(package_name, &edit_id)
+                    .await?;
+            }
+        }
+
+        result.map(|_| ())
+    }
+
+    async fn inner_assign_version(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        args: &AssignArgs,
+    ) -> Result<bool, Box<dyn Error>> {
+        let (bundle, track) = self
+            .uploaded_bundle(
+                client,
+                token,
+                package_name,
+                edit_id,
+                &args.track_name,
+                args.version_code,
+            )
+            .await?;
+
+        output!(
+            self.log,
+            "Releasing version {} [{}] to track '{}'",
+            bundle.version_code,
+            bundle.sha256_or_unknown(),
+            args.track_name
+        );
+
+        let outcome = self
+            .update_release(
+                client,
+                token,
+                package_name,
+                edit_id,
+                &track,
+                bundle.version_code,
+                args.release_name.as_deref(),
+                &[],
+                &self.config.track_defaults(&args.track_name),
+            )
+            .await?;
+
+        self.report_warnings(&outcome.warnings);
+
+        Ok(outcome.value)
+    }
+
+    /// Find a bundle that has already been uploaded, returning it along with the track it is
+    /// to be released to
+    pub(crate) async fn uploaded_bundle(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        track_name: &str,
+        version_code: i32,
+    ) -> Result<(Bundle, Track), Box<dyn Error>> {
+        let api = self.api(client, token);
+        let (bundles_list, track) = tokio::try_join!(
+            api.list_bundles(package_name, edit_id),
+            api.get_track(package_name, edit_id, track_name)
+        )?;
+        let bundle = bundles_list
+            .bundles
+            .into_iter()
+            .find(|bundle| bundle.version_code == version_code)
+            .ok_or_else(|| format!("Version {} has not been uploaded as a bundle", version_code))?;
+
+        Ok((bundle, track))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_percentage() {
+        assert_eq!(format_percentage(0.29), "29%");
+        assert_eq!(format_percentage(0.125), "12.5%");
+        assert_eq!(format_percentage(0.05), "5%");
+        assert_eq!(format_percentage(0.001), "0.1%");
+    }
+
+    #[test]
+    fn test_update_countries() {
+        let country_targeting = CountryTargeting {
+            countries: vec!["US".to_string(), "CA".to_string()],
+            include_rest_of_world: false,
+        };
+        let updated = update_countries(
+            Some(&country_targeting),
+            &["GB".to_string(), "US".to_string()],
+            &["CA".to_string()],
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(updated.countries, vec!["US", "GB"]);
+        assert!(update_countries(
+            Some(&country_targeting),
+            &[],
+            &country_targeting.countries,
+            false
+        )
+        .is_err());
+        assert_eq!(
+            update_countries(Some(&country_targeting), &["FR".to_string()], &[], true)
+                .unwrap()
+                .countries,
+            vec!["FR"]
+        );
+        // A release available in every country is never narrowed without --only
+        assert!(update_countries(None, &["FR".to_string()], &[], false).is_err());
+        assert!(update_countries(None, &[], &["FR".to_string()], false).is_err());
+        assert_eq!(
+            update_countries(None, &["FR".to_string()], &[], true).unwrap(),
+            CountryTargeting {
+                countries: vec!["FR".to_string()],
+                include_rest_of_world: false,
+            }
+        );
+        assert_eq!(parse_country("gb"), Ok("GB".to_string()));
+        assert!(parse_country("GBR").is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Settings read from `gplay.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Defaults for uploads to each track, keyed by track name
+    #[serde(default)]
+    pub tracks: HashMap<String, TrackDefaults>,
+    /// Settings for each package, keyed by package name
+    #[serde(default)]
+    pub packages: HashMap<String, PackageSettings>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrackDefaults {
+    pub status: Option<String>,
+    pub user_fraction: Option<f64>,
+    pub in_app_update_priority: Option<i32>,
+    /// An en-US release notes file for uploads that don't give their own release notes
+    pub notes: Option<PathBuf>,
+}
+
+impl TrackDefaults {
+    const STATUSES: [&str; 4] = ["draft", "inProgress", "halted", "completed"];
+
+    // Check the values that Google Play would otherwise only reject part way through an upload
+    fn validate(&self) -> Result<(), String> {
+        if let Some(status) = &self.status {
+            if !Self::STATUSES.contains(&status.as_str()) {
+                return Err(format!(
+                    "unknown status '{}', expected one of {}",
+                    status,
+                    Self::STATUSES.join(", ")
+                ));
+            }
+        }
+
+        if let Some(user_fraction) = self.user_fraction {
+            if !(user_fraction > 0.0 && user_fraction < 1.0) {
+                return Err(format!(
+                    "user_fraction {} is not a fraction between 0 and 1",
+                    user_fraction
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageSettings {
+    /// Service account key for the developer account that owns the package
+    pub cred_file: Option<PathBuf>,
+    /// The developer account ID from the Play Console URL, for links to the console
+    pub developer_id: Option<String>,
+    /// The app ID from the Play Console URL, which is not the package name
+    pub app_id: Option<String>,
+}
+
+impl Config {
+    const DEFAULT_FILE: &str = "gplay.toml";
+
+    /// Load the given config file, or `gplay.toml` in the current directory if there is one
+    pub fn load(config_file: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+        let config_file = match config_file {
+            Some(config_file) => config_file,
+            None if Path::new(Self::DEFAULT_FILE).exists() => Path::new(Self::DEFAULT_FILE),
+            None => return Ok(Config::default()),
+        };
+        let content = std::fs::read_to_string(config_file).map_err(|err| {
+            format!(
+                "Unable to read config file '{}': {}",
+                config_file.to_string_lossy(),
+                err
+            )
+        })?;
+
+        Self::parse(&content).map_err(|err| {
+            format!(
+                "Invalid config file '{}': {}",
+                config_file.to_string_lossy(),
+                err
+            )
+            .into()
+        })
+    }
+
+    fn parse(content: &str) -> Result<Config, String> {
+        let config: Config = toml::from_str(content).map_err(|err| err.to_string())?;
+        let mut track_names: Vec<&String> = config.tracks.keys().collect();
+
+        // Sorted so that the same file always reports the same track first
+        track_names.sort();
+
+        for track_name in track_names {
+            config.tracks[track_name]
+                .validate()
+                .map_err(|err| format!("track '{}' has {}", track_name, err))?;
+        }
+
+        Ok(config)
+    }
+
+    pub fn track_defaults(&self, track_name: &str) -> TrackDefaults {
+        self.tracks.get(track_name).cloned().unwrap_or_default()
+    }
+
+    pub fn credentials_file(&self, package_name: &str) -> Option<&Path> {
+        self.packages.get(package_name)?.cred_file.as_deref()
+    }
+
+    /// The Play Console developer account and app IDs of a package, if both are set
+    pub fn console_ids(&self, package_name: &str) -> Option<(&str, &str)> {
+        let package_settings = self.packages.get(package_name)?;
+
+        Some((
+            package_settings.developer_id.as_deref()?,
+            package_settings.app_id.as_deref()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config: Config = toml::from_str(
+            "[tracks.production]\nstatus = \"inProgress\"\nuser_fraction = 0.05\n\n[tracks.internal]\nstatus = \"completed\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.track_defaults("production").user_fraction,
+            Some(0.05)
+        );
+        assert_eq!(
+            config.track_defaults("internal").status.as_deref(),
+            Some("completed")
+        );
+        assert_eq!(config.track_defaults("beta").status, None);
+
+        let config: Config =
+            toml::from_str("[packages.\"com.example.app\"]\ncred_file = \"client.json\"\n")
+                .unwrap();
+
+        assert_eq!(
+            config.credentials_file("com.example.app"),
+            Some(Path::new("client.json"))
+        );
+        assert_eq!(config.credentials_file("com.example.other"), None);
+        assert_eq!(config.console_ids("com.example.app"), None);
+
+        let config: Config = toml::from_str(
+            "[packages.\"com.example.app\"]\ndeveloper_id = \"5166\"\napp_id = \"4972\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            config.console_ids("com.example.app"),
+            Some(("5166", "4972"))
+        );
+        assert!(toml::from_str::<Config>("[tracks.beta]\nfraction = 0.1\n").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let config =
+            Config::parse("[tracks.beta]\nstatus = \"completed\"\nnotes = \"notes/beta.txt\"\n")
+                .unwrap();
+
+        assert_eq!(
+            config.track_defaults("beta").notes.as_deref(),
+            Some(Path::new("notes/beta.txt"))
+        );
+        assert_eq!(
+            Config::parse("[tracks.beta]\nstatus = \"in-progress\"\n").unwrap_err(),
+            "track 'beta' has unknown status 'in-progress', expected one of draft, inProgress, halted, completed"
+        );
+        assert!(Config::parse("[tracks.beta]\nuser_fraction = 5.0\n").is_err());
+        assert!(Config::parse("[tracks.beta]\nuser_fraction = 0.0\n").is_err());
+    }
+}
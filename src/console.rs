@@ -0,0 +1,68 @@
+use crate::{output, GplayTool};
+
+const CONSOLE_URL: &str = "https://play.google.com/console/developers";
+
+// The page of a track in the Play Console. Closed testing tracks are identified by a number
+// that the API doesn't give us, so those link to the releases overview instead.
+fn track_page(track_name: &str) -> &'static str {
+    match track_name {
+        "production" => "tracks/production",
+        "beta" => "tracks/open-testing",
+        "internal" => "tracks/internal-testing",
+        _ => "releases/overview",
+    }
+}
+
+/// The Play Console URL of a page for an app
+pub fn app_url(developer_id: &str, app_id: &str, page: &str) -> String {
+    format!("{}/{}/app/{}/{}", CONSOLE_URL, developer_id, app_id, page)
+}
+
+impl GplayTool<'_> {
+    /// Print links to the Play Console for what was just changed, when the package has its
+    /// console IDs in the config file
+    pub(crate) fn output_console_links(
+        &self,
+        package_name: &str,
+        track_name: Option<&str>,
+        version_code: Option<i32>,
+    ) {
+        let (developer_id, app_id) = match self.config.console_ids(package_name) {
+            Some(console_ids) => console_ids,
+            None => return,
+        };
+        let page = track_name.map_or("releases/overview", track_page);
+
+        output!(
+            self.log,
+            "Release dashboard: {}",
+            app_url(developer_id, app_id, page)
+        );
+
+        if let Some(version_code) = version_code {
+            output!(
+                self.log,
+                "Bundle explorer: {}",
+                app_url(
+                    developer_id,
+                    app_id,
+                    &format!("bundle-explorer-selector/{}", version_code)
+                )
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_url() {
+        assert_eq!(
+            app_url("5166", "4972", track_page("internal")),
+            "https://play.google.com/console/developers/5166/app/4972/tracks/internal-testing"
+        );
+        assert_eq!(track_page("alpha"), "releases/overview");
+    }
+}
@@ -0,0 +1,147 @@
+use gcp_auth::{AuthenticationManager, CustomServiceAccount};
+use std::error::Error;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+pub type AuthenticationFuture<'p> =
+    Pin<Box<dyn Future<Output = Result<AuthenticationManager, Box<dyn Error>>> + 'p>>;
+
+/// A source of Google credentials for the Android Publisher API
+pub trait CredentialsProvider {
+    /// Where the credentials come from, for error messages
+    fn description(&self) -> String;
+    fn authentication_manager(&self) -> AuthenticationFuture<'_>;
+    /// The service account's email address, if the credentials say which account they are for
+    fn account(&self) -> Option<String> {
+        None
+    }
+}
+
+// The `client_email` of a service account key
+fn client_email(json: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(json)
+        .ok()?
+        .get("client_email")?
+        .as_str()
+        .map(|client_email| client_email.to_string())
+}
+
+/// A service account key file
+pub struct FileCredentials {
+    path: PathBuf,
+}
+
+impl FileCredentials {
+    pub fn new(path: impl Into<PathBuf>) -> FileCredentials {
+        FileCredentials { path: path.into() }
+    }
+}
+
+impl CredentialsProvider for FileCredentials {
+    fn description(&self) -> String {
+        format!("credentials file '{}'", self.path.to_string_lossy())
+    }
+
+    fn authentication_manager(&self) -> AuthenticationFuture<'_> {
+        Box::pin(async move {
+            Ok(AuthenticationManager::from(
+                CustomServiceAccount::from_file(&self.path)?,
+            ))
+        })
+    }
+
+    fn account(&self) -> Option<String> {
+        client_email(&std::fs::read_to_string(&self.path).ok()?)
+    }
+}
+
+/// A service account key held in an environment variable as JSON
+pub struct EnvCredentials {
+    var: String,
+}
+
+impl EnvCredentials {
+    pub const DEFAULT_VAR: &str = "GPLAY_CREDENTIALS_JSON";
+
+    pub fn new(var: impl Into<String>) -> EnvCredentials {
+        EnvCredentials { var: var.into() }
+    }
+}
+
+impl CredentialsProvider for EnvCredentials {
+    fn description(&self) -> String {
+        format!("environment variable {}", self.var)
+    }
+
+    fn authentication_manager(&self) -> AuthenticationFuture<'_> {
+        Box::pin(async move {
+            let json = std::env::var(&self.var)?;
+
+            Ok(AuthenticationManager::from(
+                CustomServiceAccount::from_json(&json)?,
+            ))
+        })
+    }
+
+    fn account(&self) -> Option<String> {
+        client_email(&std::env::var(&self.var).ok()?)
+    }
+}
+
+/// A service account key held in memory, e.g. after fetching it from a secret store
+pub struct JsonCredentials {
+    json: String,
+}
+
+impl JsonCredentials {
+    /// Checks that the JSON is a service account key up front
+    pub fn new(json: impl AsRef<[u8]>) -> Result<JsonCredentials, Box<dyn Error>> {
+        let json = std::str::from_utf8(json.as_ref())
+            .map_err(|_| "Credentials are not valid UTF-8")?
+            .to_string();
+
+        CustomServiceAccount::from_json(&json)?;
+
+        Ok(JsonCredentials { json })
+    }
+}
+
+impl CredentialsProvider for JsonCredentials {
+    fn description(&self) -> String {
+        "in-memory credentials".to_string()
+    }
+
+    fn authentication_manager(&self) -> AuthenticationFuture<'_> {
+        Box::pin(async move {
+            Ok(AuthenticationManager::from(
+                CustomServiceAccount::from_json(&self.json)?,
+            ))
+        })
+    }
+
+    fn account(&self) -> Option<String> {
+        client_email(&self.json)
+    }
+}
+
+/// Google application default credentials: `GOOGLE_APPLICATION_CREDENTIALS`, the gcloud
+/// CLI's credentials or the metadata server when running on Google Cloud
+pub struct ApplicationDefaultCredentials;
+
+impl CredentialsProvider for ApplicationDefaultCredentials {
+    fn description(&self) -> String {
+        "application default credentials".to_string()
+    }
+
+    fn authentication_manager(&self) -> AuthenticationFuture<'_> {
+        Box::pin(async move { Ok(AuthenticationManager::new().await?) })
+    }
+
+    // Only a key file says which account it is for, not gcloud or the metadata server
+    fn account(&self) -> Option<String> {
+        client_email(
+            &std::fs::read_to_string(std::env::var("GOOGLE_APPLICATION_CREDENTIALS").ok()?).ok()?,
+        )
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{Cli, GplayTool};
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// The error when work is cancelled because a deadline passed, which the command line exits
+/// with its own code for
+#[derive(Debug)]
+pub struct DeadlineExceeded;
+
+impl fmt::Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Deadline exceeded, cancelling remaining work")
+    }
+}
+
+impl Error for DeadlineExceeded {}
+
+impl DeadlineExceeded {
+    /// The exit code when a deadline passes, the same as `timeout(1)` uses
+    pub const EXIT_CODE: i32 = 124;
+
+    /// Whether a deadline passing caused an error
+    pub fn caused(error: &(dyn Error + 'static)) -> bool {
+        let mut source = Some(error);
+
+        while let Some(error) = source {
+            if error.is::<DeadlineExceeded>() {
+                return true;
+            }
+
+            source = error.source();
+        }
+
+        false
+    }
+}
+
+/// Parse a duration such as `20m`, `90s`, `1h` or a number of seconds
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split_at = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split_at);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid duration", s))?;
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return Err(format!("Unknown duration unit '{}', use s, m or h", unit)),
+    };
+    let duration = number
+        .checked_mul(multiplier)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("'{}' is too long a duration", s))?;
+
+    // Checked against now as well so that a deadline that can't be represented is rejected
+    // with the other parse errors, rather than when the command starts
+    deadline_after(duration).map_err(|err| err.to_string())?;
+
+    Ok(duration)
+}
+
+/// The instant that is `duration` from now
+pub(crate) fn deadline_after(duration: Duration) -> Result<Instant, Box<dyn Error>> {
+    Instant::now().checked_add(duration).ok_or_else(|| {
+        format!(
+            "A deadline {} seconds from now is too far in the future",
+            duration.as_secs()
+        )
+        .into()
+    })
+}
+
+impl GplayTool<'_> {
+    // Time allowed past the deadline for an edit to be rolled back after its step was cancelled
+    const ROLLBACK_GRACE: Duration = Duration::from_secs(15);
+
+    /// The earlier of the command's deadline and another, e.g. the timeout for an edit
+    pub(crate) fn earliest_deadline(&self, other: Option<Instant>) -> Option<Instant> {
+        match (self.deadline, other) {
+            (Some(deadline), Some(other)) => Some(deadline.min(other)),
+            (deadline, other) => deadline.or(other),
+        }
+    }
+
+    // Run a command, cancelling it if it outlasts the deadline. Uploads cancel their own steps
+    // at the deadline and roll back, so are given a little longer to do that. Edits that any
+    // other command had open are never committed and expire by themselves.
+    pub(crate) async fn run_before_deadline(&mut self, cli: Cli) -> Result<(), Box<dyn Error>> {
+        match self.deadline {
+            Some(deadline) => {
+                let deadline = deadline
+                    .checked_add(Self::ROLLBACK_GRACE)
+                    .unwrap_or(deadline);
+
+                tokio::time::timeout_at(deadline, self.run_command(cli))
+                    .await
+                    .unwrap_or_else(|_| Err(Box::new(DeadlineExceeded)))
+            }
+            None => self.run_command(cli).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::StepContext;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("20m"), Ok(Duration::from_secs(1200)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("45"), Ok(Duration::from_secs(45)));
+        assert!(parse_duration("20min").is_err());
+        assert!(parse_duration("m").is_err());
+        // Too many hours for a u64 of seconds
+        assert!(parse_duration("99999999999999999h").is_err());
+        // Representable as a duration but not as an instant from now
+        assert!(parse_duration(&u64::MAX.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_deadline_after() {
+        assert!(deadline_after(Duration::from_secs(60)).unwrap() > Instant::now());
+        assert!(deadline_after(Duration::MAX).is_err());
+    }
+
+    #[test]
+    fn test_caused() {
+        let result: Result<(), Box<dyn Error>> = Err(Box::new(DeadlineExceeded));
+        let err = result
+            .step(|| "Unable to commit edit '42'".to_string())
+            .unwrap_err();
+
+        assert!(DeadlineExceeded::caused(err.as_ref()));
+        assert!(!DeadlineExceeded::caused(
+            Box::<dyn Error>::from("Bundle is not valid").as_ref()
+        ));
+    }
+}
@@ -0,0 +1,235 @@
+use crate::step::StepError;
+use crate::ResponseError;
+use std::error::Error;
+
+/// What went wrong in a call to [`GplayClient`](crate::GplayClient), so that callers can tell
+/// e.g. a permissions problem from a failure worth retrying. The error that caused it is kept
+/// as its [`source`](Error::source).
+#[derive(Debug, thiserror::Error)]
+pub enum GplayError {
+    /// Credentials could not be loaded or exchanged for a token
+    #[error("{0}")]
+    Auth(String),
+    /// The request got no response, e.g. the connection failed or timed out
+    #[error("The request failed")]
+    Http(#[from] reqwest::Error),
+    /// The API responded with an error
+    #[error("The API returned status {code}")]
+    Api {
+        /// The HTTP status code
+        code: u16,
+        /// The API's description of the error
+        message: String,
+        /// The API's name for the error, e.g. `PERMISSION_DENIED`
+        status: Option<String>,
+        #[source]
+        source: ResponseError,
+    },
+    #[error("Unable to read or write a file")]
+    Io(#[from] std::io::Error),
+    /// A response or file could not be parsed
+    #[error("{0}")]
+    Parse(String),
+    /// A step failed, e.g. opening an edit, because of `source`
+    #[error("{context}")]
+    Context {
+        context: String,
+        #[source]
+        source: Box<GplayError>,
+    },
+    #[error("{0}")]
+    Other(String),
+}
+
+impl GplayError {
+    /// The HTTP status code of an API error
+    pub fn status_code(&self) -> Option<u16> {
+        match self {
+            GplayError::Api { code, .. } => Some(*code),
+            GplayError::Context { source, .. } => source.status_code(),
+            _ => None,
+        }
+    }
+
+    /// Whether trying again may succeed, i.e. the request didn't get through, was rate
+    /// limited or hit a server error
+    pub fn is_transient(&self) -> bool {
+        match self {
+            GplayError::Http(_) => true,
+            GplayError::Api { code, .. } => *code == 429 || *code >= 500,
+            GplayError::Context { source, .. } => source.is_transient(),
+            _ => false,
+        }
+    }
+
+    // Treat an error that isn't otherwise classified as an authentication failure
+    pub(crate) fn or_auth(self) -> GplayError {
+        match self {
+            GplayError::Other(message) => GplayError::Auth(message),
+            GplayError::Context { context, source } => GplayError::Context {
+                context,
+                source: Box::new(source.or_auth()),
+            },
+            error => error,
+        }
+    }
+}
+
+impl From<ResponseError> for GplayError {
+    fn from(error: ResponseError) -> GplayError {
+        GplayError::Api {
+            code: error.status.as_u16(),
+            message: error.to_string(),
+            status: error.error.as_ref().and_then(|error| error.status.clone()),
+            source: error,
+        }
+    }
+}
+
+impl From<serde_json::Error> for GplayError {
+    fn from(error: serde_json::Error) -> GplayError {
+        GplayError::Parse(error.to_string())
+    }
+}
+
+/// The error and all of its causes, as a single line, for messages that don't report the
+/// causes separately
+pub(crate) fn describe(error: &(dyn Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+
+    while let Some(error) = source {
+        message.push_str(": ");
+        message.push_str(&error.to_string());
+        source = error.source();
+    }
+
+    message
+}
+
+/// Take the error out of the box, keeping the errors of known kinds as the source. Steps
+/// that failed become [`GplayError::Context`] around the error that caused them.
+impl From<Box<dyn Error>> for GplayError {
+    fn from(error: Box<dyn Error>) -> GplayError {
+        let error = match error.downcast::<GplayError>() {
+            Ok(error) => return *error,
+            Err(error) => error,
+        };
+        let error = match error.downcast::<StepError>() {
+            Ok(error) => {
+                let (context, source) = error.into_parts();
+
+                return GplayError::Context {
+                    context,
+                    source: Box::new(GplayError::from(source)),
+                };
+            }
+            Err(error) => error,
+        };
+        let error = match error.downcast::<easy_error::Error>() {
+            Ok(error) => {
+                let context = error.to_string();
+
+                return match error.cause {
+                    Some(cause) => GplayError::Context {
+                        context,
+                        source: Box::new(GplayError::from(cause as Box<dyn Error>)),
+                    },
+                    None => GplayError::Other(context),
+                };
+            }
+            Err(error) => error,
+        };
+        let error = match error.downcast::<ResponseError>() {
+            Ok(error) => return GplayError::from(*error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<reqwest::Error>() {
+            Ok(error) => return GplayError::Http(*error),
+            Err(error) => error,
+        };
+        let error = match error.downcast::<std::io::Error>() {
+            Ok(error) => return GplayError::Io(*error),
+            Err(error) => error,
+        };
+        let message = describe(error.as_ref());
+        let mut source = Some(error.as_ref());
+
+        // Other errors are only looked at, so are classified by the first cause of a known kind
+        while let Some(error) = source {
+            if error.is::<gcp_auth::Error>() {
+                return GplayError::Auth(message);
+            } else if error.is::<serde_json::Error>()
+                || error.is::<serde_yaml::Error>()
+                || error.is::<toml::de::Error>()
+            {
+                return GplayError::Parse(message);
+            }
+
+            source = error.source();
+        }
+
+        GplayError::Other(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_structs::{ApiError, ApiErrorDetail};
+    use crate::step::StepContext;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_from_box() {
+        let result: Result<(), Box<dyn Error>> = Err(Box::new(ResponseError {
+            status: StatusCode::FORBIDDEN,
+            error: Some(ApiError {
+                code: Some(403),
+                message: "The caller does not have permission".to_string(),
+                status: Some("PERMISSION_DENIED".to_string()),
+                errors: vec![ApiErrorDetail {
+                    message: None,
+                    domain: None,
+                    reason: Some("forbidden".to_string()),
+                }],
+            }),
+        }));
+        let error = GplayError::from(
+            result
+                .step(|| "Unable to open an edit".to_string())
+                .unwrap_err(),
+        );
+
+        assert_eq!(error.status_code(), Some(403));
+        assert!(!error.is_transient());
+        assert_eq!(
+            describe(&error),
+            "Unable to open an edit: The API returned status 403: The caller does not have permission"
+        );
+        // Checks that look for the API's reasons still find them through the error
+        assert!(ResponseError::caused(&error, "forbidden"));
+        assert!(matches!(
+            error,
+            GplayError::Context { source, .. } if matches!(
+                *source,
+                GplayError::Api { status: Some(ref status), .. } if status == "PERMISSION_DENIED"
+            )
+        ));
+
+        let error = GplayError::from(Box::<dyn Error>::from(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "No such file",
+        )));
+
+        // The original error is kept as the source rather than only its message
+        assert!(matches!(error, GplayError::Io(_)));
+        assert!(error
+            .source()
+            .map_or(false, |source| source.is::<std::io::Error>()));
+        assert!(matches!(
+            GplayError::from(Box::<dyn Error>::from("No credentials")).or_auth(),
+            GplayError::Auth(_)
+        ));
+    }
+}
@@ -1,16 +1,84 @@
-mod api_structs;
+pub mod api;
+pub mod api_structs;
+mod bundle_file;
+mod bundle_info;
+mod cache;
+mod client;
+mod commands;
+mod config;
+mod console;
+pub mod credentials;
+mod deadline;
+mod error;
+mod log_file;
 mod log_macros;
+mod messages;
+mod outcome;
+mod pipeline;
+mod progress;
+mod rate_limit;
+mod recorder;
+mod state_store;
+mod step;
+mod strict;
+mod table;
+mod throughput;
+mod timestamps;
+mod tracks_file;
+mod upload;
 
+pub use api::{AndroidPublisherApi, ArtifactKind, ResponseError};
 use api_structs::*;
-use clap::{Parser, Subcommand};
+pub use bundle_file::BundleFile;
+use bundle_info::{BundleModule, BundleVersion, ModuleKind};
+use cache::ResponseCache;
+use clap::{Parser, Subcommand, ValueEnum};
+pub use client::{GplayClient, UploadOptions};
+use commands::apks::ApksCommands;
+use commands::bundles::{BundlesCommands, UploadArgs};
+#[cfg(feature = "games")]
+use commands::games::GamesCommands;
+use commands::reviews::{ReplyBatchArgs, ReviewsCommands};
+use commands::schema::SchemaCommands;
+use commands::token::TokenCommands;
+use commands::track::{
+    ApplyTracksArgs, AssignArgs, CompleteReleaseArgs, DescribeTrackArgs, DiffTracksArgs,
+    TrackCommands,
+};
+use config::{Config, TrackDefaults};
 use core::fmt::Arguments;
+use credentials::{
+    ApplicationDefaultCredentials, CredentialsProvider, EnvCredentials, FileCredentials,
+    JsonCredentials,
+};
+pub use deadline::DeadlineExceeded;
+use deadline::{deadline_after, parse_duration};
 use easy_error::{self, ResultExt};
-use gcp_auth::{AuthenticationManager, CustomServiceAccount, Token};
-use reqwest::{Client, Response};
-use serde::Deserialize;
+pub use error::GplayError;
+use gcp_auth::Token;
+use log_file::FileLog;
+use messages::{Catalog, MessageId};
+pub use outcome::Outcome;
+use pipeline::UploadStep;
+use progress::ProgressMode;
+use recorder::Recorder;
+use reqwest::{Client, Proxy};
+use state_store::{parse_state_location, StateLocation};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::IsTerminal;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
-use std::time::Duration;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{error::Error, path::PathBuf};
+use step::StepContext;
+use table::Table;
+pub use timestamps::TimestampLog;
+pub use upload::{ArtifactHash, ArtifactUploader, ReportedHashes, Sha256Hash, UploadedArtifact};
 
 pub trait GplayLog {
     fn output(self: &Self, args: Arguments);
@@ -20,6 +88,37 @@ pub trait GplayLog {
 
 pub struct GplayTool<'a> {
     log: &'a dyn GplayLog,
+    credentials: Option<Box<dyn CredentialsProvider>>,
+    strict_api: bool,
+    cache: Option<ResponseCache>,
+    config: Config,
+    table: Option<TableOptions>,
+    recorder: Option<Recorder>,
+    deadline: Option<tokio::time::Instant>,
+    timestamps: bool,
+    progress: ProgressMode,
+    catalog: Catalog,
+}
+
+struct TableOptions {
+    columns: Vec<String>,
+    header: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CredentialsSource {
+    /// The service account key file given with --cred-file
+    File,
+    /// A service account key in the GPLAY_CREDENTIALS_JSON environment variable
+    Env,
+    /// Google application default credentials
+    Adc,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Table,
 }
 
 #[derive(Parser)]
@@ -29,13 +128,105 @@ struct Cli {
     #[arg(long = "no-color", env = "NO_CLI_COLOR")]
     no_color: bool,
 
+    /// Config file with per-track defaults, gplay.toml in the current directory by default
+    #[arg(long = "config", value_name = "TOML-FILE", value_hint = clap::ValueHint::FilePath)]
+    config_file: Option<PathBuf>,
+
     /// Google API credentials file
     #[arg(short = 'c', long = "cred-file", value_name = "JSON-FILE", value_hint = clap::ValueHint::FilePath)]
-    credentials_file: PathBuf,
+    credentials_file: Option<PathBuf>,
+
+    /// Where to get credentials from, by default the --cred-file
+    #[arg(long = "cred-provider", value_enum, value_name = "PROVIDER")]
+    credentials_provider: Option<CredentialsSource>,
 
-    /// Google Play package name
+    /// Google Play package name, which can be repeated for read-only commands
     #[arg(short = 'n', long, value_name = "PACKAGE-NAME")]
-    package_name: String,
+    package_name: Vec<String>,
+
+    /// File containing additional package names, one per line
+    #[arg(long = "packages-file", value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+    packages_file: Option<PathBuf>,
+
+    /// Pin a host name to an IP address instead of using DNS, e.g. androidpublisher.googleapis.com=142.250.72.10
+    #[arg(long = "resolve", value_name = "HOST=IP", value_parser = parse_resolve)]
+    resolve: Vec<(String, IpAddr)>,
+
+    /// Send requests through this HTTP proxy instead of the one in HTTPS_PROXY
+    #[arg(long = "proxy", value_name = "URL")]
+    proxy: Option<String>,
+
+    /// The user name for a proxy that needs basic authentication
+    #[arg(long = "proxy-user", value_name = "USER")]
+    proxy_user: Option<String>,
+
+    /// The password for --proxy-user
+    #[arg(
+        long = "proxy-pass",
+        value_name = "PASSWORD",
+        env = "GPLAY_PROXY_PASS",
+        hide_env_values = true
+    )]
+    proxy_pass: Option<String>,
+
+    /// Warn about response fields that gplay does not know about yet
+    #[arg(long = "strict-api")]
+    strict_api: bool,
+
+    /// Let read-only commands reuse cached responses for this long, which are not cached by
+    /// default
+    #[arg(long = "cache-ttl", value_name = "SECS", default_value = "0")]
+    cache_ttl_secs: u64,
+
+    /// Ignore cached responses and fetch fresh ones
+    #[arg(long = "no-cache")]
+    no_cache: bool,
+
+    /// Keep state such as cached responses in this directory or gs://bucket/prefix, to share it between CI runners
+    #[arg(long = "state-store", value_name = "LOCATION", env = "GPLAY_STATE_STORE", value_parser = parse_state_location)]
+    state_store: Option<StateLocation>,
+
+    /// Give up on the whole command, including retries and waits, after this long, e.g. 20m. An upload's edit is rolled back and gplay exits with code 124.
+    #[arg(long = "deadline", value_name = "DURATION", value_parser = parse_duration)]
+    deadline: Option<Duration>,
+
+    /// Output format for list commands
+    #[arg(
+        long = "output",
+        value_enum,
+        value_name = "FORMAT",
+        default_value = "text"
+    )]
+    output: OutputFormat,
+
+    /// Columns to show in table output, e.g. versionCode,sha256,tracks
+    #[arg(long = "columns", value_name = "COLUMNS", value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// Leave out the header row of table output
+    #[arg(long = "no-header")]
+    no_header: bool,
+
+    /// Prefix every line of output with an ISO-8601 timestamp
+    #[arg(long = "timestamps")]
+    timestamps: bool,
+
+    /// How to show upload progress, where auto shows a bar on a terminal and lines otherwise
+    #[arg(
+        long = "progress",
+        value_enum,
+        value_name = "MODE",
+        default_value = "auto"
+    )]
+    progress: ProgressMode,
+
+    /// Record API requests and responses, without secrets, to a .tar.gz file for a bug report
+    #[arg(long = "record", value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+    record_file: Option<PathBuf>,
+
+    /// Also write everything that is logged, with timestamps, to this file
+    #[arg(long = "log-file", value_name = "FILE", value_hint = clap::ValueHint::FilePath)]
+    log_file: Option<PathBuf>,
 
     #[command(subcommand)]
     command: Option<Commands>,
@@ -43,37 +234,236 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Lists uploaded bundle versions
-    ListBundles,
-    /// List available release tracks
-    ListTracks,
-    /// Upload a new bundle
-    Upload {
-        /// The bundle file to upload
+    /// Work with bundles
+    Bundles {
+        #[command(subcommand)]
+        command: BundlesCommands,
+    },
+    /// Work with release tracks
+    Track {
+        #[command(subcommand)]
+        command: TrackCommands,
+    },
+    /// Work with user reviews
+    Reviews {
+        #[command(subcommand)]
+        command: ReviewsCommands,
+    },
+    /// Work with APKs
+    Apks {
+        #[command(subcommand)]
+        command: ApksCommands,
+    },
+    /// Work with the Play Games Services configuration of a game
+    #[cfg(feature = "games")]
+    Games {
+        #[command(subcommand)]
+        command: GamesCommands,
+    },
+    /// Print the JSON Schemas of the files that gplay reads
+    Schema {
+        #[command(subcommand)]
+        command: SchemaCommands,
+    },
+    /// Work with OAuth access tokens
+    Token {
+        #[command(subcommand)]
+        command: TokenCommands,
+    },
+    /// Check credentials, permissions and network access in an edit that is then discarded
+    Selftest {
+        /// A bundle to upload to the internal track as part of the test
         #[arg(short = 'b', long = "bundle-file", value_name = "AAB-FILE", value_hint = clap::ValueHint::FilePath)]
-        aab_file: PathBuf,
-        /// The name of the track to add the bundle too
-        #[arg(short = 'n', long = "track-name", value_name = "NAME")]
-        track_name: String,
-        /// The timeout for the upload in seconds
-        #[arg(
-            short = 't',
-            long = "timeout",
-            value_name = "TIMEOUT-SECS",
-            default_value = "300"
-        )]
-        timeout_secs: u64,
+        aab_file: Option<PathBuf>,
     },
+    /// Roll out a track's staged release to all users, the same as 'track complete'
+    Complete(CompleteReleaseArgs),
+    /// Release an already uploaded version to a track, the same as 'track assign'
+    Assign(AssignArgs),
+    // The flat commands from before there were namespaces, kept for existing scripts
+    #[command(hide = true)]
+    ListBundles,
+    #[command(hide = true)]
+    UnusedBundles,
+    #[command(hide = true)]
+    Upload(UploadArgs),
+    #[command(hide = true)]
+    ListTracks,
+    #[command(hide = true)]
+    DescribeTrack(DescribeTrackArgs),
+    #[command(hide = true)]
+    DiffTracks(DiffTracksArgs),
+    #[command(hide = true)]
+    ApplyTracks(ApplyTracksArgs),
+    #[command(hide = true)]
+    ReviewStats,
+    #[command(hide = true)]
+    ReplyReviews(ReplyBatchArgs),
+}
+
+impl Commands {
+    // Map the flat commands onto their namespaced equivalents
+    fn namespaced(self) -> Commands {
+        match self {
+            Commands::ListBundles => Commands::Bundles {
+                command: BundlesCommands::List,
+            },
+            Commands::UnusedBundles => Commands::Bundles {
+                command: BundlesCommands::Unused,
+            },
+            Commands::Upload(args) => Commands::Bundles {
+                command: BundlesCommands::Upload(args),
+            },
+            Commands::ListTracks => Commands::Track {
+                command: TrackCommands::List,
+            },
+            Commands::DescribeTrack(args) => Commands::Track {
+                command: TrackCommands::Describe(args),
+            },
+            Commands::DiffTracks(args) => Commands::Track {
+                command: TrackCommands::Diff(args),
+            },
+            Commands::ApplyTracks(args) => Commands::Track {
+                command: TrackCommands::Apply(args),
+            },
+            Commands::Complete(args) => Commands::Track {
+                command: TrackCommands::Complete(args),
+            },
+            Commands::Assign(args) => Commands::Track {
+                command: TrackCommands::Assign(args),
+            },
+            Commands::ReviewStats => Commands::Reviews {
+                command: ReviewsCommands::Stats,
+            },
+            Commands::ReplyReviews(args) => Commands::Reviews {
+                command: ReviewsCommands::ReplyBatch(args),
+            },
+            command => command,
+        }
+    }
+}
+
+fn parse_resolve(s: &str) -> Result<(String, IpAddr), String> {
+    let (host, ip) = s
+        .split_once('=')
+        .ok_or_else(|| format!("'{}' is not of the form HOST=IP", s))?;
+    let ip = ip
+        .parse::<IpAddr>()
+        .map_err(|_| format!("'{}' is not a valid IP address", ip))?;
+
+    Ok((host.to_string(), ip))
+}
+
+/// A bundle that has been uploaded and released, and the edit it was committed in
+#[derive(Debug, Clone)]
+pub struct UploadResult {
+    pub edit_id: String,
+    pub bundle: Bundle,
 }
 
 impl<'a> GplayTool<'a> {
-    const EDIT_URL: &str =
-        "https://androidpublisher.googleapis.com/androidpublisher/v3/applications";
-    const UPLOAD_URL: &str =
-        "https://androidpublisher.googleapis.com/upload/androidpublisher/v3/applications";
+    const ANDROID_PUBLISHER_SCOPE: &str = "https://www.googleapis.com/auth/androidpublisher";
+    const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+    const BAR_INTERVAL: Duration = Duration::from_millis(500);
+    const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+    const SELF_TEST_TRACK: &str = "internal";
+    const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(300);
+    // Bundles that may install larger than this need their upload to acknowledge it
+    const INSTALL_WARNING_SIZE: u64 = 100_000_000;
 
     pub fn new(log: &'a dyn GplayLog) -> GplayTool {
-        GplayTool { log }
+        GplayTool {
+            log,
+            credentials: None,
+            strict_api: false,
+            cache: None,
+            config: Config::default(),
+            table: None,
+            recorder: None,
+            deadline: None,
+            timestamps: false,
+            progress: ProgressMode::Plain,
+            catalog: Catalog::from_env(),
+        }
+    }
+
+    /// Create a tool that authenticates with service account credentials held in memory,
+    /// e.g. fetched from a secret store, instead of reading them from `--cred-file`.
+    pub fn from_credentials_json(
+        log: &'a dyn GplayLog,
+        json: impl AsRef<[u8]>,
+    ) -> Result<GplayTool, Box<dyn Error>> {
+        Ok(Self::with_credentials(log, JsonCredentials::new(json)?))
+    }
+
+    /// Create a tool that gets its credentials from the given provider when no credentials
+    /// are given on the command line
+    pub fn with_credentials(
+        log: &'a dyn GplayLog,
+        credentials: impl CredentialsProvider + 'static,
+    ) -> GplayTool {
+        GplayTool {
+            credentials: Some(Box::new(credentials)),
+            ..Self::new(log)
+        }
+    }
+
+    /// Whether the last run was asked for `--timestamps`, e.g. to stamp its error the same way
+    pub fn timestamps(&self) -> bool {
+        self.timestamps
+    }
+
+    // A single client is shared by every request in a command so that connections, and their
+    // TLS sessions, are reused across the open, upload, track and commit steps of an edit.
+    fn new_client(
+        resolve: &[(String, IpAddr)],
+        proxy: Option<Proxy>,
+    ) -> Result<Client, Box<dyn Error>> {
+        let mut builder = Client::builder()
+            .pool_idle_timeout(Self::POOL_IDLE_TIMEOUT)
+            .tcp_keepalive(Self::POOL_IDLE_TIMEOUT);
+
+        // Otherwise reqwest uses the proxies from the environment
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+
+        for (host, ip) in resolve.iter() {
+            // The port is ignored by reqwest, which uses the default port for the URL scheme
+            builder = builder.resolve(host, SocketAddr::new(*ip, 443));
+        }
+
+        Ok(builder.build()?)
+    }
+
+    // The proxy given on the command line. Authenticating needs the proxy URL, so it is taken
+    // from the environment when only the user is given.
+    fn proxy(
+        url: Option<&str>,
+        user: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<Option<Proxy>, Box<dyn Error>> {
+        let env_url = || {
+            ["HTTPS_PROXY", "https_proxy"]
+                .iter()
+                .find_map(|name| std::env::var(name).ok())
+        };
+        let url = match (url, user) {
+            (Some(url), _) => url.to_string(),
+            (None, Some(_)) => {
+                env_url().ok_or("--proxy-user needs a proxy, given with --proxy or HTTPS_PROXY")?
+            }
+            (None, None) => return Ok(None),
+        };
+        let proxy = Proxy::all(&url).step(|| format!("Invalid proxy URL '{}'", url))?;
+
+        Ok(Some(match user {
+            Some(user) => proxy.basic_auth(
+                user,
+                password.ok_or("--proxy-user needs --proxy-pass or GPLAY_PROXY_PASS")?,
+            ),
+            None => proxy,
+        }))
     }
 
     pub async fn run(
@@ -88,95 +478,607 @@ impl<'a> GplayTool<'a> {
             }
         };
 
-        output!(
-            self.log,
-            "Requesting OAuth token with Android Publisher scope"
+        self.timestamps = cli.timestamps;
+
+        let timestamp_log = cli.timestamps.then(|| TimestampLog::new(self.log));
+        let console_log: &dyn GplayLog = match &timestamp_log {
+            Some(timestamp_log) => timestamp_log,
+            None => self.log,
+        };
+        let file_log = match &cli.log_file {
+            Some(log_file) => Some(FileLog::create(log_file, console_log)?),
+            None => None,
+        };
+
+        if timestamp_log.is_none() && file_log.is_none() {
+            return self.run_cli(cli).await;
+        }
+
+        let log: &dyn GplayLog = match &file_log {
+            Some(file_log) => file_log,
+            None => console_log,
+        };
+        let mut tool = GplayTool {
+            credentials: self.credentials.take(),
+            ..GplayTool::new(log)
+        };
+        let result = tool.run_cli(cli).await;
+
+        if let (Some(file_log), Err(error)) = (&file_log, &result) {
+            file_log.write_error(&**error);
+        }
+
+        result
+    }
+
+    async fn run_cli(self: &mut Self, cli: Cli) -> Result<(), Box<dyn Error>> {
+        // Schemas are printed without needing a config, packages or credentials
+        if let Some(Commands::Schema {
+            command: SchemaCommands::Print(args),
+        }) = &cli.command
+        {
+            self.print_schema(args.format);
+            return Ok(());
+        }
+
+        self.deadline = cli.deadline.map(deadline_after).transpose()?;
+
+        let record_file = match &cli.record_file {
+            Some(record_file) => record_file.clone(),
+            None => return self.run_before_deadline(cli).await,
+        };
+
+        self.recorder = Some(Recorder::default());
+
+        let result = self.run_before_deadline(cli).await;
+
+        if let Some(recorder) = self.recorder.take() {
+            match recorder.write(&record_file) {
+                Ok(()) => output!(
+                    self.log,
+                    "Recorded {} API calls to '{}'",
+                    recorder.num_exchanges(),
+                    record_file.to_string_lossy()
+                ),
+                Err(err) => warning!(self.log, "Unable to write recorded API calls: {}", err),
+            }
+        }
+
+        result
+    }
+
+    async fn run_command(self: &mut Self, cli: Cli) -> Result<(), Box<dyn Error>> {
+        self.config = Config::load(cli.config_file.as_deref())?;
+
+        // Packages with their own credentials in the config file don't need these
+        let default_credentials: Option<Box<dyn CredentialsProvider>> = match (
+            cli.credentials_provider,
+            cli.credentials_file,
+            self.credentials.take(),
+        ) {
+            (Some(CredentialsSource::Env), _, _) => {
+                Some(Box::new(EnvCredentials::new(EnvCredentials::DEFAULT_VAR)))
+            }
+            (Some(CredentialsSource::Adc), _, _) => Some(Box::new(ApplicationDefaultCredentials)),
+            (_, Some(credentials_file), _) => {
+                Some(Box::new(FileCredentials::new(credentials_file)))
+            }
+            (Some(CredentialsSource::File), None, _) => {
+                return Err("A credentials file must be given with --cred-file".into())
+            }
+            (None, None, credentials) => credentials,
+        };
+        let mut default_token = None;
+
+        if let Some(Commands::Token {
+            command: TokenCommands::Print(args),
+        }) = &cli.command
+        {
+            if cli.log_file.is_some() {
+                return Err(
+                    "Tokens can't be printed with --log-file, which would write them to disk"
+                        .into(),
+                );
+            }
+
+            return self
+                .print_token(
+                    cli.package_name
+                        .first()
+                        .map(|package_name| package_name.as_str()),
+                    default_credentials.as_deref(),
+                    &args.scopes,
+                )
+                .await;
+        }
+
+        let proxy = Self::proxy(
+            cli.proxy.as_deref(),
+            cli.proxy_user.as_deref(),
+            cli.proxy_pass.as_deref(),
+        )?;
+        let client = Self::new_client(&cli.resolve, proxy)?;
+
+        self.strict_api = cli.strict_api;
+        self.cache = self
+            .open_state_store(
+                cli.state_store.as_ref(),
+                default_credentials.as_deref(),
+                &client,
+            )
+            .await?
+            .map(|store| {
+                ResponseCache::new(
+                    store,
+                    Duration::from_secs(cli.cache_ttl_secs),
+                    cli.no_cache,
+                    default_credentials
+                        .as_deref()
+                        .and_then(|credentials| credentials.account()),
+                )
+            });
+        self.progress = cli.progress.resolve(
+            std::io::stderr().is_terminal(),
+            cli.timestamps || cli.log_file.is_some(),
         );
+        self.table = (cli.output == OutputFormat::Table).then(|| TableOptions {
+            columns: cli.columns.clone(),
+            header: !cli.no_header,
+        });
 
-        let service_account = CustomServiceAccount::from_file(cli.credentials_file)?;
-        let authentication_manager = AuthenticationManager::from(service_account);
-        let token = authentication_manager
-            .get_token(&["https://www.googleapis.com/auth/androidpublisher"])
-            .await?;
+        // Games are identified by their application ID rather than a package name
+        #[cfg(feature = "games")]
+        {
+            if let Some(Commands::Games { command }) = &cli.command {
+                let credentials = default_credentials
+                    .as_deref()
+                    .ok_or("A credentials file must be given with --cred-file")?;
+                let token = self.get_token(credentials).await?;
 
-        match &cli.command {
-            Some(Commands::ListBundles) => {
-                self.list_bundles(&token, &cli.package_name).await?;
+                return self.run_games(&client, &token, command).await;
             }
-            Some(Commands::ListTracks) => {
-                self.list_tracks(&token, &cli.package_name).await?;
+        }
+
+        let package_names = Self::package_names(&cli.package_name, cli.packages_file.as_deref())?;
+
+        let command = match cli.command {
+            Some(command) => command.namespaced(),
+            None => return Ok(()),
+        };
+
+        match &command {
+            Commands::Bundles {
+                command: BundlesCommands::Upload(args),
+            } => {
+                let package_name = Self::single_package_name(&package_names, "bundles upload")?;
+                let token = self
+                    .package_token(
+                        package_name,
+                        default_credentials.as_deref(),
+                        &mut default_token,
+                    )
+                    .await?;
+
+                self.upload(&client, &token, package_name, args).await?;
             }
-            Some(Commands::Upload {
-                aab_file,
-                track_name,
-                timeout_secs,
-            }) => {
-                self.upload_bundle(
+            Commands::Apks {
+                command: ApksCommands::Upload(args),
+            } => {
+                let package_name = Self::single_package_name(&package_names, "apks upload")?;
+                let token = self
+                    .package_token(
+                        package_name,
+                        default_credentials.as_deref(),
+                        &mut default_token,
+                    )
+                    .await?;
+
+                self.upload_apk(&client, &token, package_name, args).await?;
+            }
+            Commands::Bundles {
+                command: BundlesCommands::UploadSymbols(args),
+            } => {
+                let package_name =
+                    Self::single_package_name(&package_names, "bundles upload-symbols")?;
+                let token = self
+                    .package_token(
+                        package_name,
+                        default_credentials.as_deref(),
+                        &mut default_token,
+                    )
+                    .await?;
+
+                self.upload_symbols(&client, &token, package_name, args)
+                    .await?;
+            }
+            Commands::Track {
+                command: TrackCommands::Apply(args),
+            } => {
+                let package_name = Self::single_package_name(&package_names, "track apply")?;
+                let token = self
+                    .package_token(
+                        package_name,
+                        default_credentials.as_deref(),
+                        &mut default_token,
+                    )
+                    .await?;
+
+                self.apply_tracks(
+                    &client,
                     &token,
-                    &cli.package_name,
-                    aab_file,
-                    track_name,
-                    *timeout_secs,
+                    package_name,
+                    &args.tracks_file,
+                    args.dry_run,
+                )
+                .await?;
+            }
+            Commands::Track {
+                command: TrackCommands::Complete(args),
+            } => {
+                let package_name = Self::single_package_name(&package_names, "track complete")?;
+                let token = self
+                    .package_token(
+                        package_name,
+                        default_credentials.as_deref(),
+                        &mut default_token,
+                    )
+                    .await?;
+
+                self.complete_release(
+                    &client,
+                    &token,
+                    package_name,
+                    &args.track_name,
+                    args.release_name.as_deref(),
+                )
+                .await?;
+            }
+            Commands::Track {
+                command: TrackCommands::Countries(args),
+            } => {
+                let package_name = Self::single_package_name(&package_names, "track countries")?;
+                let token = self
+                    .package_token(
+                        package_name,
+                        default_credentials.as_deref(),
+                        &mut default_token,
+                    )
+                    .await?;
+
+                self.update_release_countries(&client, &token, package_name, args)
+                    .await?;
+            }
+            Commands::Track {
+                command: TrackCommands::SetRollout(args),
+            } => {
+                let package_name = Self::single_package_name(&package_names, "track set-rollout")?;
+                let token = self
+                    .package_token(
+                        package_name,
+                        default_credentials.as_deref(),
+                        &mut default_token,
+                    )
+                    .await?;
+
+                self.set_rollout(
+                    &client,
+                    &token,
+                    package_name,
+                    &args.track_name,
+                    args.fraction,
+                    args.release_name.as_deref(),
                 )
                 .await?;
             }
-            None => {}
+            Commands::Track {
+                command: TrackCommands::Assign(args),
+            } => {
+                let package_name = Self::single_package_name(&package_names, "track assign")?;
+                let token = self
+                    .package_token(
+                        package_name,
+                        default_credentials.as_deref(),
+                        &mut default_token,
+                    )
+                    .await?;
+
+                self.assign_version(&client, &token, package_name, args)
+                    .await?;
+            }
+            Commands::Reviews {
+                command: ReviewsCommands::ReplyBatch(args),
+            } => {
+                let package_name =
+                    Self::single_package_name(&package_names, "reviews reply-batch")?;
+                let token = self
+                    .package_token(
+                        package_name,
+                        default_credentials.as_deref(),
+                        &mut default_token,
+                    )
+                    .await?;
+
+                self.reply_reviews(&client, &token, package_name, &args.replies_file)
+                    .await?;
+            }
+            command => {
+                let mut num_failed = 0;
+
+                for package_name in package_names.iter() {
+                    if package_names.len() > 1 {
+                        output!(self.log, "Package '{}'", package_name);
+                    }
+
+                    let result = match self
+                        .package_token(
+                            package_name,
+                            default_credentials.as_deref(),
+                            &mut default_token,
+                        )
+                        .await
+                    {
+                        Ok(token) => {
+                            self.run_read_only(command, &client, &token, package_name)
+                                .await
+                        }
+                        Err(err) => Err(err),
+                    };
+
+                    if let Err(err) = result {
+                        if package_names.len() == 1 {
+                            return Err(err);
+                        }
+
+                        error!(self.log, "{}", err);
+                        num_failed += 1;
+                    }
+                }
+
+                if num_failed > 0 {
+                    return Err(format!(
+                        "Command failed for {} of {} packages",
+                        num_failed,
+                        package_names.len()
+                    )
+                    .into());
+                }
+            }
         }
 
         Ok(())
     }
 
-    // Can we use PhantomData here?  Check the length of the returned body and return that instead?
-    async fn get_response<T: for<'de> Deserialize<'de>>(
-        response: Response,
-    ) -> Result<T, Box<dyn Error>> {
-        let status = response.status();
+    async fn get_token(
+        &self,
+        credentials: &dyn CredentialsProvider,
+    ) -> Result<Token, Box<dyn Error>> {
+        output!(
+            self.log,
+            "Requesting OAuth token with Android Publisher scope"
+        );
 
-        if status.is_success() {
-            Ok(response.json::<T>().await?)
-        } else {
-            if let Ok(error) = response.json::<ErrorResponse>().await {
-                Err(error.error.message.into())
-            } else {
-                Err(status.to_string().into())
+        self.authenticate(credentials, &[Self::ANDROID_PUBLISHER_SCOPE])
+            .await
+    }
+
+    async fn authenticate(
+        &self,
+        credentials: &dyn CredentialsProvider,
+        scopes: &[&str],
+    ) -> Result<Token, Box<dyn Error>> {
+        let authentication_manager = credentials
+            .authentication_manager()
+            .await
+            .step(|| format!("Unable to load {}", credentials.description()))?;
+
+        authentication_manager
+            .get_token(scopes)
+            .await
+            .step(|| "Unable to get an OAuth token for the service account".to_string())
+    }
+
+    // Use the package's own credentials from the config file if it has them, otherwise the
+    // default credentials, which are only authenticated once
+    async fn package_token(
+        &self,
+        package_name: &str,
+        default_credentials: Option<&dyn CredentialsProvider>,
+        default_token: &mut Option<Token>,
+    ) -> Result<Token, Box<dyn Error>> {
+        if let Some(credentials_file) = self.config.credentials_file(package_name) {
+            return self
+                .get_token(&FileCredentials::new(credentials_file))
+                .await;
+        }
+
+        if let Some(token) = default_token {
+            return Ok(token.clone());
+        }
+
+        let credentials = default_credentials.ok_or_else(|| {
+            format!(
+                "A credentials file must be given with --cred-file, or for package '{}' in the config file",
+                package_name
+            )
+        })?;
+        let token = self.get_token(credentials).await?;
+
+        *default_token = Some(token.clone());
+
+        Ok(token)
+    }
+
+    // Read-only commands can be run against several packages in turn
+    async fn run_read_only(
+        &self,
+        command: &Commands,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        match command {
+            Commands::Bundles { command } => match command {
+                BundlesCommands::List => self.list_bundles(client, token, package_name).await,
+                BundlesCommands::Unused => self.unused_bundles(client, token, package_name).await,
+                BundlesCommands::Upload(_) | BundlesCommands::UploadSymbols(_) => unreachable!(),
+            },
+            Commands::Track { command } => match command {
+                TrackCommands::List => self.list_tracks(client, token, package_name).await,
+                TrackCommands::Describe(args) => {
+                    self.describe_track(
+                        client,
+                        token,
+                        package_name,
+                        &args.track_name,
+                        args.release_status.as_deref(),
+                    )
+                    .await
+                }
+                TrackCommands::Diff(args) => {
+                    self.diff_tracks(client, token, package_name, &args.from, &args.to)
+                        .await
+                }
+                TrackCommands::Apply(_)
+                | TrackCommands::Complete(_)
+                | TrackCommands::Countries(_)
+                | TrackCommands::SetRollout(_)
+                | TrackCommands::Assign(_) => unreachable!(),
+            },
+            Commands::Reviews { command } => match command {
+                ReviewsCommands::Stats => self.review_stats(client, token, package_name).await,
+                ReviewsCommands::ReplyBatch(_) => unreachable!(),
+            },
+            Commands::Apks {
+                command: ApksCommands::List,
+            } => self.list_apks(client, token, package_name).await,
+            Commands::Selftest { aab_file } => {
+                self.self_test(client, token, package_name, aab_file.as_deref())
+                    .await
             }
+            _ => unreachable!(),
+        }
+    }
+
+    fn package_names(
+        package_names: &[String],
+        packages_file: Option<&Path>,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut names = package_names.to_vec();
+
+        if let Some(packages_file) = packages_file {
+            let content =
+                std::fs::read_to_string(packages_file).context("Unable to read packages file")?;
+
+            names.extend(
+                content
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|line| line.to_string()),
+            );
         }
+
+        if names.is_empty() {
+            return Err("A package name must be given with --package-name".into());
+        }
+
+        Ok(names)
     }
 
-    async fn get_empty_response(response: Response) -> Result<(), Box<dyn Error>> {
-        let status = response.status();
+    fn single_package_name<'b>(
+        package_names: &'b [String],
+        command_name: &str,
+    ) -> Result<&'b str, Box<dyn Error>> {
+        match package_names {
+            [package_name] => Ok(package_name),
+            _ => Err(format!(
+                "The {} command requires exactly one package name",
+                command_name
+            )
+            .into()),
+        }
+    }
 
-        if status.is_success() {
-            Ok(())
+    // Hooks are best effort; a failing hook does not change the outcome of the command
+    async fn run_hook(&self, command: &str, env: &[(&str, String)]) {
+        let mut shell = if cfg!(windows) {
+            let mut shell = tokio::process::Command::new("cmd");
+
+            shell.arg("/C");
+            shell
         } else {
-            if let Ok(error) = response.json::<ErrorResponse>().await {
-                Err(error.error.message.into())
-            } else {
-                Err(status.to_string().into())
-            }
+            let mut shell = tokio::process::Command::new("sh");
+
+            shell.arg("-c");
+            shell
+        };
+
+        output!(self.log, "Running hook '{}'", command);
+
+        match shell
+            .arg(command)
+            .envs(env.iter().map(|(key, value)| (key, value)))
+            .status()
+            .await
+        {
+            Ok(status) if status.success() => {}
+            Ok(status) => warning!(self.log, "Hook '{}' exited with {}", command, status),
+            Err(err) => warning!(self.log, "Unable to run hook '{}': {}", command, err),
         }
     }
 
+    // A message from the catalog for the user's language
+    fn message(&self, id: MessageId, args: &[&dyn std::fmt::Display]) -> String {
+        self.catalog.format(id, args)
+    }
+
+    fn report_warnings(&self, warnings: &[String]) {
+        if warnings.is_empty() {
+            return;
+        }
+
+        warning!(
+            self.log,
+            "{}",
+            self.message(MessageId::CompletedWithWarnings, &[&warnings.len()])
+        );
+
+        for message in warnings.iter() {
+            warning!(self.log, "  {}", message);
+        }
+    }
+
+    fn api<'b>(&'b self, client: &'b Client, token: &'b Token) -> AndroidPublisherApi<'b> {
+        AndroidPublisherApi::new(client, token, self.log)
+            .strict(self.strict_api)
+            .recorder(self.recorder.as_ref())
+    }
+
     async fn open_edit(
         &self,
         client: &Client,
         token: &Token,
         package_name: &str,
     ) -> Result<String, Box<dyn Error>> {
-        Ok(Self::get_response::<EditInsert>(
-            client
-                .post(format!(
-                    "{}/{package_name}/edits",
-                    Self::EDIT_URL,
-                    package_name = package_name
-                ))
-                .bearer_auth(token.as_str())
-                .body("{}")
-                .send()
-                .await?,
-        )
-        .await?
-        .id)
+        Ok(self.api(client, token).insert_edit(package_name).await?.id)
+    }
+
+    // Time left before the edit expires, if the API told us when that is
+    fn edit_time_remaining(edit: &EditInsert) -> Option<Duration> {
+        let expiry_secs = edit.expiry_time_seconds.as_ref()?.parse::<u64>().ok()?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+
+        Some(Duration::from_secs(expiry_secs).saturating_sub(now))
+    }
+
+    fn edit_expired(edit: &EditInsert) -> bool {
+        Self::edit_time_remaining(edit).map_or(false, |remaining| remaining.is_zero())
+    }
+
+    // Whether an error came from the edit expiring, either by its expiry time or because the
+    // API said so, in which case there is nothing left to roll back
+    fn edit_expired_error(edit: &EditInsert, err: &(dyn Error + 'static)) -> bool {
+        Self::edit_expired(edit) || ResponseError::caused(err, "editExpired")
     }
 
     async fn commit_edit(
@@ -186,207 +1088,918 @@ impl<'a> GplayTool<'a> {
         package_name: &str,
         edit_id: &str,
     ) -> Result<(), Box<dyn Error>> {
-        Self::get_empty_response(
-            client
-                .post(format!(
-                    "{}/{package_name}/edits/{edit_id}:commit",
-                    Self::EDIT_URL,
-                    package_name = package_name,
-                    edit_id = edit_id
-                ))
-                .bearer_auth(token.as_str())
-                .header("Content-Length", 0)
-                .send()
-                .await?,
-        )
-        .await
+        match self
+            .api(client, token)
+            .commit_edit(package_name, edit_id)
+            .await
+        {
+            Ok(()) => (),
+            // An earlier attempt went through even though we never heard back
+            Err(err) if ResponseError::caused(&err, "editAlreadyCommitted") => {
+                warning!(self.log, "Edit '{}' was already committed", edit_id)
+            }
+            Err(err) if ResponseError::caused(&err, "editExpired") => {
+                return Err(err).step(|| {
+                    format!(
+                        "Edit '{}' expired before being committed, run the command again",
+                        edit_id
+                    )
+                })
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        // Cached responses for the package no longer reflect the live state
+        if let Some(cache) = &self.cache {
+            cache.invalidate(package_name).await;
+        }
+
+        Ok(())
+    }
+
+    // The cache along with the account that the package's responses are fetched with. Nothing
+    // is cached for credentials that don't say which account they are for.
+    fn response_cache(&self, package_name: &str) -> Option<(&ResponseCache, String)> {
+        let cache = self.cache.as_ref()?;
+        let account = match self.config.credentials_file(package_name) {
+            Some(credentials_file) => FileCredentials::new(credentials_file).account(),
+            None => cache.default_account().map(|account| account.to_string()),
+        }?;
+
+        Some((cache, account))
     }
 
-    async fn delete_edit(
+    // Read the bundles and tracks of a package in a throwaway edit, or from the cache
+    async fn read_bundles_and_tracks(
         &self,
         client: &Client,
         token: &Token,
         package_name: &str,
-        edit_id: &str,
-    ) -> Result<(), Box<dyn Error>> {
-        Self::get_empty_response(
-            client
-                .delete(format!(
-                    "{}/{package_name}/edits/{edit_id}",
-                    Self::EDIT_URL,
-                    package_name = package_name,
-                    edit_id = edit_id
-                ))
-                .bearer_auth(token.as_str())
-                .send()
-                .await?,
-        )
-        .await
-    }
+    ) -> Result<(EditBundlesList, TracksList), Box<dyn Error>> {
+        let cache = self.response_cache(package_name);
 
-    async fn list_bundles(&self, token: &Token, package_name: &str) -> Result<(), Box<dyn Error>> {
-        let client = reqwest::Client::new();
-        let edit_id = self.open_edit(&client, token, package_name).await?;
-        let edit_bundles_list = Self::get_response::<EditBundlesList>(
-            client
-                .get(format!(
-                    "{}/{package_name}/edits/{edit_id}/bundles",
-                    Self::EDIT_URL,
-                    package_name = package_name,
-                    edit_id = edit_id
-                ))
-                .bearer_auth(token.as_str())
-                .send()
-                .await?,
-        )
-        .await?;
+        if let Some((cache, account)) = &cache {
+            if let (Some(edit_bundles_list), Some(tracks_list)) = tokio::join!(
+                cache.get::<EditBundlesList>(package_name, account, "bundles"),
+                cache.get::<TracksList>(package_name, account, "tracks"),
+            ) {
+                return Ok((edit_bundles_list, tracks_list));
+            }
+        }
 
-        for bundle in edit_bundles_list.bundles.iter() {
-            output!(
-                self.log,
-                "Version {} [{}]",
-                bundle.version_code,
-                bundle.sha256
+        let api = self.api(client, token);
+        let edit_id = api.insert_edit(package_name).await?.id;
+        // The bundles and tracks don't depend on each other, so are fetched together
+        let (edit_bundles_list, tracks_list) = tokio::try_join!(
+            api.list_bundles(package_name, &edit_id),
+            api.list_tracks(package_name, &edit_id)
+        )?;
+
+        api.delete_edit(package_name, &edit_id).await?;
+
+        if let Some((cache, account)) = &cache {
+            tokio::join!(
+                cache.put(package_name, account, "bundles", &edit_bundles_list),
+                cache.put(package_name, account, "tracks", &tracks_list),
             );
         }
 
-        self.delete_edit(&client, token, package_name, &edit_id)
+        Ok((edit_bundles_list, tracks_list))
+    }
+
+    async fn read_tracks(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+    ) -> Result<TracksList, Box<dyn Error>> {
+        let cache = self.response_cache(package_name);
+
+        if let Some((cache, account)) = &cache {
+            if let Some(tracks_list) = cache
+                .get::<TracksList>(package_name, account, "tracks")
+                .await
+            {
+                return Ok(tracks_list);
+            }
+        }
+
+        let edit_id = self.open_edit(client, token, package_name).await?;
+        let tracks_list = self
+            .api(client, token)
+            .list_tracks(package_name, &edit_id)
             .await?;
 
-        Ok(())
+        self.api(client, token)
+            .delete_edit(package_name, &edit_id)
+            .await?;
+
+        if let Some((cache, account)) = &cache {
+            cache
+                .put(package_name, account, "tracks", &tracks_list)
+                .await;
+        }
+
+        Ok(tracks_list)
     }
 
-    async fn list_tracks(&self, token: &Token, package_name: &str) -> Result<(), Box<dyn Error>> {
-        let client = reqwest::Client::new();
-        let edit_id = self.open_edit(&client, token, package_name).await?;
-        let tracks_list = Self::get_response::<TracksList>(
-            client
-                .get(format!(
-                    "{}/{package_name}/edits/{edit_id}/tracks",
-                    Self::EDIT_URL,
-                    package_name = package_name,
-                    edit_id = edit_id
-                ))
-                .bearer_auth(token.as_str())
-                .send()
-                .await?,
-        )
-        .await?;
+    // Map each version code to the names of the tracks that have a release referencing it
+    fn version_code_tracks(tracks_list: &TracksList) -> HashMap<String, Vec<String>> {
+        let mut map: HashMap<String, Vec<String>> = HashMap::new();
 
         for track in tracks_list.tracks.iter() {
-            output!(self.log, "Track '{}'", track.name);
+            for release in track.releases.iter() {
+                if let Some(version_codes) = &release.version_codes {
+                    for version_code in version_codes.iter() {
+                        let track_names = map.entry(version_code.clone()).or_default();
+
+                        if !track_names.contains(&track.name) {
+                            track_names.push(track.name.clone());
+                        }
+                    }
+                }
+            }
         }
 
-        self.delete_edit(&client, token, package_name, &edit_id)
-            .await?;
+        map
+    }
+
+    fn output_table(&self, table: &Table) -> Result<(), Box<dyn Error>> {
+        let (columns, header) = match &self.table {
+            Some(table_options) => (table_options.columns.as_slice(), table_options.header),
+            None => (&[][..], true),
+        };
+
+        for line in table.render(columns, header)? {
+            output!(self.log, "{}", line);
+        }
 
         Ok(())
     }
 
-    async fn inner_upload_bundle(
+    // Run through a whole edit without committing it, so nothing in the package changes
+    async fn self_test(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        aab_file: Option<&Path>,
+    ) -> Result<(), Box<dyn Error>> {
+        let edit_id = self
+            .open_edit(client, token, package_name)
+            .await
+            .step(|| format!("Unable to open an edit for package '{}'", package_name))?;
+
+        output!(self.log, "Opened edit '{}'", edit_id);
+
+        let result = self
+            .inner_self_test(client, token, package_name, &edit_id, aab_file)
+            .await;
+
+        self.api(client, token)
+            .delete_edit(package_name, &edit_id)
+            .await
+            .step(|| format!("Unable to delete edit '{}'", edit_id))?;
+        output!(self.log, "Deleted edit '{}'", edit_id);
+        result?;
+        output!(self.log, "Self test passed");
+
+        Ok(())
+    }
+
+    async fn inner_self_test(
         &self,
         client: &Client,
         token: &Token,
         package_name: &str,
         edit_id: &str,
-        aab_file: &Path,
-        track_name: &str,
-        timeout_secs: u64,
+        aab_file: Option<&Path>,
     ) -> Result<(), Box<dyn Error>> {
-        let byte_buf = std::fs::read(aab_file).context("Unable to read bundle file")?;
+        let bundles_list = self
+            .api(client, token)
+            .list_bundles(package_name, edit_id)
+            .await
+            .step(|| format!("Unable to list bundles in edit '{}'", edit_id))?;
+
+        output!(self.log, "Listed {} bundles", bundles_list.bundles.len());
+
+        let tracks_list = self
+            .api(client, token)
+            .list_tracks(package_name, edit_id)
+            .await
+            .step(|| format!("Unable to list tracks in edit '{}'", edit_id))?;
+
+        output!(self.log, "Listed {} tracks", tracks_list.tracks.len());
+
+        if let Some(aab_file) = aab_file {
+            let bundle_file = self.open_bundle(aab_file)?;
+            let release_name = Self::default_release_name(&bundle_file);
+            let outcome = self
+                .inner_upload_bundle(
+                    client,
+                    token,
+                    package_name,
+                    edit_id,
+                    &bundle_file,
+                    Self::SELF_TEST_TRACK,
+                    release_name.as_deref(),
+                    &[],
+                    &self.config.track_defaults(Self::SELF_TEST_TRACK),
+                    Self::SELF_TEST_TIMEOUT.as_secs(),
+                    upload::DEFAULT_CHUNK_SIZE,
+                    None,
+                    false,
+                    false,
+                )
+                .await?;
+
+            self.report_warnings(&outcome.warnings);
+        }
+
+        self.api(client, token)
+            .validate_edit(package_name, edit_id)
+            .await
+            .step(|| format!("Unable to validate edit '{}'", edit_id))?;
+        output!(self.log, "Validated edit '{}'", edit_id);
+
+        Ok(())
+    }
+
+    // A bundle file of '-' means read the bundle from standard input
+    fn open_bundle(&self, aab_file: &Path) -> Result<BundleFile, Box<dyn Error>> {
+        let bundle_file = BundleFile::open(aab_file).step(|| {
+            format!(
+                "Unable to open bundle file '{}'",
+                aab_file.to_string_lossy()
+            )
+        })?;
 
         output!(
             self.log,
-            "Read bundle file '{}' ({} bytes), uploading...",
+            "Opened bundle file '{}' ({} bytes)",
             aab_file.to_string_lossy(),
-            byte_buf.len()
+            bundle_file.size()
         );
 
-        let bundle = Self::get_response::<Bundle>(
-            client
-                .post(format!(
-                    "{}/{package_name}/edits/{edit_id}/bundles?uploadType=media",
-                    Self::UPLOAD_URL,
-                    package_name = package_name,
-                    edit_id = edit_id
-                ))
-                .timeout(Duration::from_secs(timeout_secs))
-                .bearer_auth(token.as_str())
-                .header("Content-Type", "application/octet-stream")
-                .header("Content-Length", byte_buf.len())
-                .body(byte_buf)
-                .send()
-                .await?,
+        // Show what is in the bundle so an unexpected module is noticed before release
+        match bundle_file.modules() {
+            Ok(modules) => {
+                for module in modules.iter() {
+                    output!(
+                        self.log,
+                        "Module '{}' ({}, {} bytes)",
+                        module.name,
+                        module.kind,
+                        module.size
+                    );
+                }
+
+                let asset_packs: Vec<&BundleModule> = modules
+                    .iter()
+                    .filter(|module| module.kind == ModuleKind::AssetPack)
+                    .collect();
+
+                if !asset_packs.is_empty() {
+                    output!(
+                        self.log,
+                        "{} asset packs totalling {} bytes",
+                        asset_packs.len(),
+                        asset_packs.iter().map(|module| module.size).sum::<u64>()
+                    );
+                }
+            }
+            Err(err) => warning!(self.log, "Unable to list bundle modules: {}", err),
+        }
+
+        Ok(bundle_file)
+    }
+
+    // Refuse to put a lower version code on a track than it already has, which Google
+    // would otherwise only reject at commit time
+    fn check_downgrade(
+        track: &Track,
+        version_code: i64,
+        allow_downgrade: bool,
+        outcome: &mut Outcome<()>,
+    ) -> Result<(), Box<dyn Error>> {
+        let highest_version_code = track
+            .releases
+            .iter()
+            .filter_map(|release| release.version_codes.as_ref())
+            .flatten()
+            .filter_map(|version_code| version_code.parse::<i64>().ok())
+            .max();
+
+        match highest_version_code {
+            Some(highest_version_code) if highest_version_code > version_code => {
+                let message = format!(
+                    "Version {} is lower than version {} already on track '{}'",
+                    version_code, highest_version_code, track.name
+                );
+
+                if allow_downgrade {
+                    outcome.warn(message);
+                    Ok(())
+                } else {
+                    Err(format!("{}, use --allow-downgrade to upload it anyway", message).into())
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Name releases like "1.4.2 (10402)" from the bundle manifest, as the Play Console does
+    fn default_release_name(bundle_file: &BundleFile) -> Option<String> {
+        match bundle_file.version().ok()? {
+            BundleVersion {
+                version_name: Some(version_name),
+                version_code: Some(version_code),
+            } => Some(format!("{} ({})", version_name, version_code)),
+            _ => None,
+        }
+    }
+
+    // The version code from the bundle's manifest, if it can be read
+    fn local_version_code(bundle_file: &BundleFile) -> Option<i64> {
+        bundle_file
+            .version()
+            .ok()
+            .and_then(|version| version.version_code)
+            .and_then(|version_code| version_code.parse::<i64>().ok())
+    }
+
+    // Upload a bundle to an edit, checking it against the releases the track already has, which
+    // are returned along with the uploaded bundle
+    async fn upload_to_edit(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        bundle_file: &BundleFile,
+        track_name: &str,
+        timeout_secs: u64,
+        chunk_size: u64,
+        max_upload_rate: Option<u64>,
+        allow_downgrade: bool,
+        ack_install_warning: bool,
+    ) -> Result<Outcome<(Bundle, Track)>, Box<dyn Error>> {
+        let mut outcome = Outcome::new(());
+        let existing_track = self
+            .api(client, token)
+            .get_track(package_name, edit_id, track_name)
+            .await
+            .step(|| {
+                format!(
+                    "Unable to read track '{}' in edit '{}'",
+                    track_name, edit_id
+                )
+            })?;
+        let local_version_code = Self::local_version_code(bundle_file);
+
+        // Check before uploading when the bundle manifest tells us the version code
+        if let Some(version_code) = local_version_code {
+            Self::check_downgrade(&existing_track, version_code, allow_downgrade, &mut outcome)?;
+        }
+
+        output!(self.log, "Uploading {} bytes...", bundle_file.size());
+
+        let num_bytes = bundle_file.size();
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        let mut uploader = ArtifactUploader::new(
+            self.api(client, token),
+            self.log,
+            ArtifactKind::Bundle {
+                ack_install_warning,
+            },
         )
-        .await?;
+        .chunk_size(chunk_size)
+        .timeout(Duration::from_secs(timeout_secs))
+        .max_rate(max_upload_rate)
+        .progress(bytes_sent.clone())
+        .verify(Sha256Hash::default());
+        let send = uploader.upload::<Bundle>(package_name, edit_id, bundle_file);
+        let start = Instant::now();
+        let upload_step = || format!("Unable to upload bundle to edit '{}'", edit_id);
+        let progress_interval = match self.progress {
+            ProgressMode::Bar => Self::BAR_INTERVAL,
+            _ => Self::PROGRESS_INTERVAL,
+        };
+        let mut interval = tokio::time::interval(progress_interval);
+        let mut last_bytes_sent = 0;
+        let mut bar_drawn = false;
+
+        tokio::pin!(send);
+        // The first tick completes immediately
+        interval.tick().await;
+
+        let bundle = loop {
+            tokio::select! {
+                bundle = &mut send => break bundle,
+                _ = interval.tick() => {
+                    // Resuming after a failed chunk takes the count back to what the server
+                    // has, which can be less than last time
+                    let sent = bytes_sent.load(Ordering::Relaxed);
+                    let rate = throughput::format_byte_rate(
+                        sent.saturating_sub(last_bytes_sent),
+                        progress_interval,
+                    );
+
+                    match self.progress {
+                        ProgressMode::Bar => {
+                            progress::draw_bar(&progress::format_bar(sent, num_bytes, &rate));
+                            bar_drawn = true;
+                        }
+                        ProgressMode::None => (),
+                        _ => output!(self.log, "Sent {} of {} bytes ({})", sent, num_bytes, rate),
+                    }
+
+                    last_bytes_sent = sent;
+                }
+            }
+        };
+
+        if bar_drawn {
+            progress::finish_bar();
+        }
+
+        let bundle = bundle.step(upload_step)?;
+
+        output!(
+            self.log,
+            "Sent {} bytes in {:.1} seconds ({} average)",
+            num_bytes,
+            start.elapsed().as_secs_f64(),
+            throughput::format_byte_rate(num_bytes, start.elapsed())
+        );
 
         output!(
             self.log,
             "Version {} [{}] uploaded",
             bundle.version_code,
-            bundle.sha256
+            bundle.sha256_or_unknown()
         );
 
-        Self::get_response::<Track>(
-            client
-                .put(format!(
-                    "{}/{package_name}/edits/{edit_id}/tracks/{track_name}",
-                    Self::EDIT_URL,
-                    package_name = package_name,
-                    edit_id = edit_id,
-                    track_name = track_name
-                ))
-                .bearer_auth(token.as_str())
-                .json(&Track {
-                    name: track_name.to_string(),
-                    releases: vec![Release {
-                        status: "draft".to_string(),
-                        version_codes: Some(vec![bundle.version_code.to_string()]),
-                    }],
-                })
-                .send()
-                .await?,
-        )
-        .await?;
+        if local_version_code.is_none() {
+            Self::check_downgrade(
+                &existing_track,
+                bundle.version_code as i64,
+                allow_downgrade,
+                &mut outcome,
+            )?;
+        }
 
-        Ok(())
+        Ok(outcome.map(|_| (bundle, existing_track)))
     }
 
-    async fn upload_bundle(
+    // Replace the track's releases with a new release of the uploaded bundle
+    async fn update_release(
         &self,
+        client: &Client,
         token: &Token,
         package_name: &str,
-        aab_file: &Path,
+        edit_id: &str,
+        existing_track: &Track,
+        version_code: i32,
+        release_name: Option<&str>,
+        release_notes: &[LocalizedText],
+        track_defaults: &TrackDefaults,
+    ) -> Result<Outcome<bool>, Box<dyn Error>> {
+        let track_name = existing_track.name.as_str();
+        let mut outcome = Outcome::new(());
+        let mut release = Release::builder()
+            .status(track_defaults.status.as_deref().unwrap_or("draft"))
+            .version_code(version_code);
+
+        if let Some(release_name) = release_name {
+            release = release.name(release_name);
+        }
+
+        for release_note in release_notes.iter() {
+            release = release.release_note(&release_note.language, &release_note.text);
+        }
+
+        if let Some(user_fraction) = track_defaults.user_fraction {
+            release = release.user_fraction(user_fraction);
+        }
+
+        if let Some(priority) = track_defaults.in_app_update_priority {
+            release = release.in_app_update_priority(priority);
+        }
+
+        let track = Track::new(track_name, existing_track.releases_with(release.build()));
+
+        // Updating the track to what it already is would leave nothing to commit
+        if track.releases == existing_track.releases {
+            return Ok(outcome.map(|_| false));
+        }
+
+        for release in existing_track
+            .releases
+            .iter()
+            .filter(|release| release.status == "draft")
+        {
+            outcome.warn(format!(
+                "Track '{}' had an existing draft release for versions [{}] which was replaced",
+                track_name,
+                release
+                    .version_codes
+                    .as_ref()
+                    .map(|version_codes| version_codes.join(", "))
+                    .unwrap_or_default()
+            ));
+        }
+
+        self.api(client, token)
+            .update_track(package_name, edit_id, &track)
+            .await
+            .step(|| {
+                format!(
+                    "Unable to update track '{}' in edit '{}'",
+                    track_name, edit_id
+                )
+            })?;
+
+        Ok(outcome.map(|_| true))
+    }
+
+    // Upload a bundle to an open edit and add it to the track, without committing the edit
+    async fn inner_upload_bundle(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        bundle_file: &BundleFile,
         track_name: &str,
+        release_name: Option<&str>,
+        release_notes: &[LocalizedText],
+        track_defaults: &TrackDefaults,
         timeout_secs: u64,
-    ) -> Result<(), Box<dyn Error>> {
-        let client = reqwest::Client::new();
-        let edit_id = self.open_edit(&client, token, package_name).await?;
-
-        let result = self
-            .inner_upload_bundle(
-                &client,
+        chunk_size: u64,
+        max_upload_rate: Option<u64>,
+        allow_downgrade: bool,
+        ack_install_warning: bool,
+    ) -> Result<Outcome<Bundle>, Box<dyn Error>> {
+        let mut outcome = Outcome::new(());
+        let (bundle, existing_track) = outcome.absorb(
+            self.upload_to_edit(
+                client,
                 token,
                 package_name,
-                &edit_id,
-                aab_file,
+                edit_id,
+                bundle_file,
                 track_name,
                 timeout_secs,
+                chunk_size,
+                max_upload_rate,
+                allow_downgrade,
+                ack_install_warning,
             )
+            .await?,
+        );
+
+        outcome.absorb(
+            self.update_release(
+                client,
+                token,
+                package_name,
+                edit_id,
+                &existing_track,
+                bundle.version_code,
+                release_name,
+                release_notes,
+                track_defaults,
+            )
+            .await?,
+        );
+
+        Ok(outcome.map(|_| bundle))
+    }
+
+    // Run a step of an edit, failing it if the deadline for the whole operation passes first
+    async fn with_deadline<T>(
+        deadline: Option<tokio::time::Instant>,
+        step: impl Future<Output = Result<T, Box<dyn Error>>>,
+    ) -> Result<T, Box<dyn Error>> {
+        match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, step)
+                .await
+                .unwrap_or_else(|_| Err(Box::new(DeadlineExceeded))),
+            None => step.await,
+        }
+    }
+
+    // Run the steps of an upload that follow opening the edit, returning the step that failed
+    // along with its error
+    async fn upload_steps(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        edit_id: &str,
+        bundle_file: &BundleFile,
+        track_name: &str,
+        release_name: Option<&str>,
+        release_notes: &[LocalizedText],
+        mapping: Option<&BundleFile>,
+        track_defaults: &TrackDefaults,
+        timeout_secs: u64,
+        chunk_size: u64,
+        max_upload_rate: Option<u64>,
+        allow_downgrade: bool,
+        ack_install_warning: bool,
+        assign_if_exists: bool,
+        deadline: Option<tokio::time::Instant>,
+    ) -> Result<Outcome<Bundle>, (UploadStep, Box<dyn Error>)> {
+        let mut outcome = Outcome::new(());
+        let uploaded = self
+            .run_step(UploadStep::Upload, deadline, || async move {
+                self.upload_to_edit(
+                    client,
+                    token,
+                    package_name,
+                    edit_id,
+                    bundle_file,
+                    track_name,
+                    timeout_secs,
+                    chunk_size,
+                    max_upload_rate,
+                    allow_downgrade,
+                    ack_install_warning,
+                )
+                .await
+            })
             .await;
+        let used_version_code = match &uploaded {
+            Err(err) if ResponseError::caused(err.as_ref(), "apkUpgradeVersionConflict") => {
+                Self::local_version_code(bundle_file)
+            }
+            _ => None,
+        };
+        let (bundle, existing_track) = match (uploaded, used_version_code) {
+            (Ok(uploaded), _) => outcome.absorb(uploaded),
+            (Err(_), Some(version_code)) if assign_if_exists => {
+                outcome.warn(format!(
+                    "Version {} had already been uploaded and was released instead",
+                    version_code
+                ));
+                self.uploaded_bundle(
+                    client,
+                    token,
+                    package_name,
+                    edit_id,
+                    track_name,
+                    version_code as i32,
+                )
+                .await
+                .map_err(|err| (UploadStep::Upload, err))?
+            }
+            (Err(err), Some(version_code)) => {
+                return Err(err)
+                    .step(|| {
+                        format!(
+                            "Version {} has already been uploaded, release it with 'gplay assign --version-code {} --track {}' or upload with --assign-if-exists",
+                            version_code, version_code, track_name
+                        )
+                    })
+                    .map_err(|err| (UploadStep::Upload, err))
+            }
+            (Err(err), None) => return Err((UploadStep::Upload, err)),
+        };
 
-        if let Ok(_) = result {
-            output!(self.log, "Committing upload");
-            self.commit_edit(&client, token, package_name, &edit_id)
-                .await?;
-        } else {
-            self.delete_edit(&client, token, package_name, &edit_id)
+        let existing_track = &existing_track;
+        let version_code = bundle.version_code;
+
+        if let Some(mapping) = mapping {
+            self.run_step(UploadStep::AttachArtifacts, deadline, || async move {
+                ArtifactUploader::new(
+                    self.api(client, token),
+                    self.log,
+                    ArtifactKind::DeobfuscationFile {
+                        version_code,
+                        file_type: "proguard".to_string(),
+                    },
+                )
+                .timeout(Duration::from_secs(timeout_secs))
+                .upload::<DeobfuscationFilesUploadResponse>(package_name, edit_id, mapping)
+                .await
+                .step(|| {
+                    format!(
+                        "Unable to upload the mapping file for version {}",
+                        version_code
+                    )
+                })
+            })
+            .await
+            .map_err(|err| (UploadStep::AttachArtifacts, err))?;
+
+            output!(
+                self.log,
+                "Attached mapping file to version {}",
+                version_code
+            );
+        }
+
+        let changed = outcome.absorb(
+            self.run_step(UploadStep::UpdateTrack, deadline, || async move {
+                self.update_release(
+                    client,
+                    token,
+                    package_name,
+                    edit_id,
+                    existing_track,
+                    version_code,
+                    release_name,
+                    release_notes,
+                    track_defaults,
+                )
+                .await
+            })
+            .await
+            .map_err(|err| (UploadStep::UpdateTrack, err))?,
+        );
+
+        // Only a version that was already uploaded can already be released like this, and a
+        // mapping file attached to it still needs committing
+        if !changed && mapping.is_none() {
+            output!(
+                self.log,
+                "{}",
+                self.message(MessageId::NoChangesRelease, &[&track_name])
+            );
+
+            if let Err(err) = self
+                .api(client, token)
+                .delete_edit(package_name, edit_id)
+                .await
+            {
+                warning!(
+                    self.log,
+                    "Unable to delete edit '{}': {}",
+                    edit_id,
+                    error::describe(&err)
+                );
+            }
+
+            return Ok(outcome.map(|_| bundle));
+        }
+
+        self.run_step(UploadStep::Validate, deadline, || async move {
+            self.api(client, token)
+                .validate_edit(package_name, edit_id)
+                .await
+                .step(|| format!("Edit '{}' is not valid", edit_id))
+        })
+        .await
+        .map_err(|err| (UploadStep::Validate, err))?;
+
+        self.run_step(UploadStep::Commit, deadline, || async move {
+            self.commit_edit(client, token, package_name, edit_id)
+                .await
+                .step(|| format!("Unable to commit edit '{}'", edit_id))
+        })
+        .await
+        .map_err(|err| (UploadStep::Commit, err))?;
+
+        Ok(outcome.map(|_| bundle))
+    }
+
+    async fn upload_bundle(
+        &self,
+        client: &Client,
+        token: &Token,
+        package_name: &str,
+        aab_file: &Path,
+        mapping_file: Option<&Path>,
+        track_name: &str,
+        release_name: Option<&str>,
+        release_notes: &[LocalizedText],
+        track_defaults: &TrackDefaults,
+        timeout_secs: u64,
+        chunk_size: u64,
+        max_upload_rate: Option<u64>,
+        edit_timeout_secs: Option<u64>,
+        allow_downgrade: bool,
+        ack_install_warning: bool,
+        assign_if_exists: bool,
+    ) -> Result<Outcome<UploadResult>, Box<dyn Error>> {
+        let bundle_file = self.open_bundle(aab_file)?;
+        let mapping = match mapping_file {
+            Some(mapping_file) => Some(BundleFile::open(mapping_file).step(|| {
+                format!(
+                    "Unable to open mapping file '{}'",
+                    mapping_file.to_string_lossy()
+                )
+            })?),
+            None => None,
+        };
+
+        if !ack_install_warning && bundle_file.size() > Self::INSTALL_WARNING_SIZE {
+            warning!(
+                self.log,
+                "Bundle is over {} MB, Google Play may require --ack-install-warning",
+                Self::INSTALL_WARNING_SIZE / 1_000_000
+            );
+        }
+        let release_name = release_name
+            .map(|release_name| release_name.to_string())
+            .or_else(|| Self::default_release_name(&bundle_file));
+        let deadline = self.earliest_deadline(
+            edit_timeout_secs
+                .map(|secs| deadline_after(Duration::from_secs(secs)))
+                .transpose()?,
+        );
+        let open_edit = || async move {
+            self.api(client, token)
+                .insert_edit(package_name)
+                .await
+                .step(|| format!("Unable to open an edit for package '{}'", package_name))
+        };
+        let mut edit = self
+            .run_step(UploadStep::OpenEdit, deadline, &open_edit)
+            .await?;
+
+        if let Some(remaining) = Self::edit_time_remaining(&edit) {
+            if remaining < Duration::from_secs(timeout_secs) {
+                warning!(
+                    self.log,
+                    "Edit expires in {} seconds, which is less than the upload timeout",
+                    remaining.as_secs()
+                );
+            }
+        }
+
+        let mut is_retry = false;
+        let result = loop {
+            let result = self
+                .upload_steps(
+                    client,
+                    token,
+                    package_name,
+                    &edit.id,
+                    &bundle_file,
+                    track_name,
+                    release_name.as_deref(),
+                    release_notes,
+                    mapping.as_ref(),
+                    track_defaults,
+                    timeout_secs,
+                    chunk_size,
+                    max_upload_rate,
+                    allow_downgrade,
+                    ack_install_warning,
+                    assign_if_exists,
+                    deadline,
+                )
+                .await;
+
+            if is_retry
+                || !matches!(&result, Err((_, err)) if Self::edit_expired_error(&edit, err.as_ref()))
+            {
+                break result;
+            }
+
+            // Uploads cannot be moved between edits, so start over with a fresh one
+            warning!(
+                self.log,
+                "Edit '{}' expired before the upload was committed, retrying with a new edit",
+                edit.id
+            );
+            edit = self
+                .run_step(UploadStep::OpenEdit, deadline, &open_edit)
                 .await?;
-            // Return the error from the failed upload
-            return result;
+            is_retry = true;
+        };
+
+        if let Err((step, err)) = &result {
+            if step.rolls_back() && !Self::edit_expired_error(&edit, err.as_ref()) {
+                output!(self.log, "Rolling back edit '{}'", edit.id);
+
+                if let Err(err) = self
+                    .api(client, token)
+                    .delete_edit(package_name, &edit.id)
+                    .await
+                {
+                    warning!(
+                        self.log,
+                        "Unable to delete edit '{}': {}",
+                        edit.id,
+                        error::describe(&err)
+                    );
+                }
+            }
         }
 
-        Ok(())
+        // Return the outcome, or the error from the step that failed
+        result
+            .map(|outcome| {
+                outcome.map(|bundle| UploadResult {
+                    edit_id: edit.id.clone(),
+                    bundle,
+                })
+            })
+            .map_err(|(_, err)| err)
     }
 }
 
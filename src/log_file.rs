@@ -0,0 +1,65 @@
+use crate::timestamps::format_timestamp;
+use crate::GplayLog;
+use core::fmt::Arguments;
+use easy_error::ResultExt;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A log that passes every line on to another log and also appends it, timestamped, to a file
+pub struct FileLog<'a> {
+    inner: &'a dyn GplayLog,
+    file: Mutex<File>,
+}
+
+impl<'a> FileLog<'a> {
+    pub fn create(path: &Path, inner: &'a dyn GplayLog) -> Result<FileLog<'a>, Box<dyn Error>> {
+        Ok(FileLog {
+            inner,
+            file: Mutex::new(File::create(path).context("Unable to create log file")?),
+        })
+    }
+
+    /// Record the error that ended the run, with its chain of causes
+    pub fn write_error(&self, error: &dyn Error) {
+        self.write_line("error: ", format_args!("{}", error));
+
+        let mut source = error.source();
+
+        while let Some(cause) = source {
+            self.write_line("  caused by: ", format_args!("{}", cause));
+            source = cause.source();
+        }
+    }
+
+    // A log file that can't be written to shouldn't stop a release, so failures are ignored
+    fn write_line(&self, prefix: &str, args: Arguments) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{} {}{}",
+                format_timestamp(SystemTime::now()),
+                prefix,
+                args
+            );
+        }
+    }
+}
+
+impl GplayLog for FileLog<'_> {
+    fn output(self: &Self, args: Arguments) {
+        self.write_line("", args);
+        self.inner.output(args);
+    }
+    fn warning(self: &Self, args: Arguments) {
+        self.write_line("warning: ", args);
+        self.inner.warning(args);
+    }
+    fn error(self: &Self, args: Arguments) {
+        self.write_line("error: ", args);
+        self.inner.error(args);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt::Display;
+
+/// The messages in the catalog, which are the status lines that commands finish with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MessageId {
+    CommitTrackChanges,
+    CommitCompletedRelease,
+    CommitCountryTargeting,
+    CommitRollout,
+    CommitRelease,
+    CommitReleaseOfVersion,
+    CommitSymbols,
+    NoChangesTracks,
+    NoChangesCountries,
+    NoChangesRollout,
+    NoChangesRelease,
+    CompletedWithWarnings,
+}
+
+type Messages = &'static [(MessageId, &'static str)];
+
+// English is the default, and the fallback for any message that another catalog lacks
+const EN: Messages = &[
+    (
+        MessageId::CommitTrackChanges,
+        "Committing {0} track change(s)",
+    ),
+    (
+        MessageId::CommitCompletedRelease,
+        "Committing completed release",
+    ),
+    (
+        MessageId::CommitCountryTargeting,
+        "Committing country targeting",
+    ),
+    (MessageId::CommitRollout, "Committing rollout"),
+    (MessageId::CommitRelease, "Committing release"),
+    (
+        MessageId::CommitReleaseOfVersion,
+        "Committing release of version {0}",
+    ),
+    (MessageId::CommitSymbols, "Committing native debug symbols"),
+    (
+        MessageId::NoChangesTracks,
+        "No changes, tracks are up to date",
+    ),
+    (
+        MessageId::NoChangesCountries,
+        "No changes, the release already targets these countries",
+    ),
+    (
+        MessageId::NoChangesRollout,
+        "No changes, the release is already rolled out that far",
+    ),
+    (
+        MessageId::NoChangesRelease,
+        "No changes, track '{0}' already has this release",
+    ),
+    (
+        MessageId::CompletedWithWarnings,
+        "Completed with {0} warning(s):",
+    ),
+];
+
+// Catalogs by language code. A translation is added here as another table of the same ids.
+const CATALOGS: &[(&str, Messages)] = &[("en", EN)];
+
+/// The messages for one language, chosen with `GPLAY_LANG` or the usual locale variables
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Catalog {
+    messages: Messages,
+}
+
+impl Default for Catalog {
+    fn default() -> Catalog {
+        Catalog { messages: EN }
+    }
+}
+
+impl Catalog {
+    /// The catalog for a locale such as `de_DE.UTF-8` or `de-DE`, or English if there is none
+    pub(crate) fn for_locale(locale: &str) -> Catalog {
+        let language = locale
+            .split(['_', '-', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        CATALOGS
+            .iter()
+            .find(|(code, _)| *code == language)
+            .map_or_else(Catalog::default, |(_, messages)| Catalog { messages })
+    }
+
+    pub(crate) fn from_env() -> Catalog {
+        ["GPLAY_LANG", "LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+            .map_or_else(Catalog::default, |locale| Catalog::for_locale(&locale))
+    }
+
+    /// The message with `{0}`, `{1}` and so on replaced by the arguments
+    pub(crate) fn format(&self, id: MessageId, args: &[&dyn Display]) -> String {
+        let template = Self::find(self.messages, id)
+            .or_else(|| Self::find(EN, id))
+            .unwrap_or_default();
+        let mut message = template.to_string();
+
+        for (i, arg) in args.iter().enumerate() {
+            message = message.replace(&format!("{{{}}}", i), &arg.to_string());
+        }
+
+        message
+    }
+
+    fn find(messages: Messages, id: MessageId) -> Option<&'static str> {
+        messages
+            .iter()
+            .find(|(message_id, _)| *message_id == id)
+            .map(|(_, template)| *template)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let catalog = Catalog::default();
+
+        assert_eq!(
+            catalog.format(MessageId::NoChangesRelease, &[&"beta"]),
+            "No changes, track 'beta' already has this release"
+        );
+        assert_eq!(
+            catalog.format(MessageId::CommitTrackChanges, &[&2]),
+            "Committing 2 track change(s)"
+        );
+    }
+
+    #[test]
+    fn test_for_locale() {
+        // There is only English so far, which every locale falls back to
+        assert_eq!(
+            Catalog::for_locale("de_DE.UTF-8").format(MessageId::CommitRollout, &[]),
+            "Committing rollout"
+        );
+        assert_eq!(
+            Catalog::for_locale("en-GB").format(MessageId::CommitRollout, &[]),
+            "Committing rollout"
+        );
+    }
+
+    #[test]
+    fn test_catalogs_are_complete() {
+        for (code, messages) in CATALOGS.iter() {
+            assert_eq!(messages.len(), EN.len(), "catalog '{}'", code);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+/// The result of an operation that succeeded, along with any non-fatal warnings
+/// accumulated along the way so they can be reported together at the end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome<T> {
+    pub value: T,
+    pub warnings: Vec<String>,
+}
+
+impl<T> Outcome<T> {
+    pub fn new(value: T) -> Outcome<T> {
+        Outcome {
+            value,
+            warnings: vec![],
+        }
+    }
+
+    pub fn warn(&mut self, message: impl Into<String>) {
+        self.warnings.push(message.into());
+    }
+
+    /// Take the warnings from another outcome, returning its value
+    pub fn absorb<U>(&mut self, other: Outcome<U>) -> U {
+        self.warnings.extend(other.warnings);
+        other.value
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Outcome<U> {
+        Outcome {
+            value: f(self.value),
+            warnings: self.warnings,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use crate::api::ResponseError;
+use crate::{output, warning, GplayTool};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+/// The steps of an upload, in the order they run. Each has its own policy for retrying
+/// transient failures and for whether the edit is rolled back when it fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadStep {
+    OpenEdit,
+    Upload,
+    AttachArtifacts,
+    UpdateTrack,
+    Validate,
+    Commit,
+}
+
+impl UploadStep {
+    pub const ALL: [UploadStep; 6] = [
+        UploadStep::OpenEdit,
+        UploadStep::Upload,
+        UploadStep::AttachArtifacts,
+        UploadStep::UpdateTrack,
+        UploadStep::Validate,
+        UploadStep::Commit,
+    ];
+    const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+    /// How many times the step is retried after a transient failure. The upload resumes
+    /// failed chunks itself rather than starting again, while the other steps are safe to
+    /// repeat.
+    pub fn retries(self) -> usize {
+        match self {
+            UploadStep::Upload => 0,
+            UploadStep::OpenEdit
+            | UploadStep::AttachArtifacts
+            | UploadStep::UpdateTrack
+            | UploadStep::Validate
+            | UploadStep::Commit => 2,
+        }
+    }
+
+    /// Whether the edit should be deleted when the step fails. There is no edit to delete if
+    /// opening one failed.
+    pub fn rolls_back(self) -> bool {
+        self != UploadStep::OpenEdit
+    }
+
+    fn number(self) -> usize {
+        UploadStep::ALL
+            .iter()
+            .position(|step| *step == self)
+            .unwrap_or_default()
+            + 1
+    }
+}
+
+impl fmt::Display for UploadStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            UploadStep::OpenEdit => "open edit",
+            UploadStep::Upload => "upload",
+            UploadStep::AttachArtifacts => "attach artifacts",
+            UploadStep::UpdateTrack => "update track",
+            UploadStep::Validate => "validate",
+            UploadStep::Commit => "commit",
+        })
+    }
+}
+
+/// Whether an error is worth retrying, i.e. a network failure or a server error
+pub fn is_transient(error: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(error);
+
+    while let Some(error) = source {
+        if let Some(reqwest_error) = error.downcast_ref::<reqwest::Error>() {
+            if reqwest_error.is_connect() || reqwest_error.is_timeout() {
+                return true;
+            }
+        }
+
+        if let Some(response_error) = error.downcast_ref::<ResponseError>() {
+            if response_error.status.is_server_error() {
+                return true;
+            }
+        }
+
+        source = error.source();
+    }
+
+    false
+}
+
+impl GplayTool<'_> {
+    /// Run a step of an upload, reporting its progress and retrying it as its policy allows.
+    /// `run` is called again for each attempt.
+    pub(crate) async fn run_step<T, F, Fut>(
+        &self,
+        step: UploadStep,
+        deadline: Option<tokio::time::Instant>,
+        run: F,
+    ) -> Result<T, Box<dyn Error>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, Box<dyn Error>>>,
+    {
+        output!(
+            self.log,
+            "Step {} of {}: {}",
+            step.number(),
+            UploadStep::ALL.len(),
+            step
+        );
+
+        let mut num_retries = 0;
+
+        loop {
+            match Self::with_deadline(deadline, run()).await {
+                Err(err) if num_retries < step.retries() && is_transient(err.as_ref()) => {
+                    num_retries += 1;
+                    warning!(
+                        self.log,
+                        "Step '{}' failed, retrying ({} of {}): {}",
+                        step,
+                        num_retries,
+                        step.retries(),
+                        err
+                    );
+                    tokio::time::sleep(UploadStep::RETRY_DELAY * num_retries as u32).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::step::StepContext;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn test_policies() {
+        assert_eq!(UploadStep::OpenEdit.number(), 1);
+        assert_eq!(UploadStep::Commit.number(), 6);
+        assert_eq!(UploadStep::Upload.retries(), 0);
+        assert!(!UploadStep::OpenEdit.rolls_back());
+        assert!(UploadStep::Commit.rolls_back());
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let server_error: Result<(), Box<dyn Error>> = Err(Box::new(ResponseError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            error: None,
+        }));
+        let client_error: Result<(), Box<dyn Error>> = Err(Box::new(ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            error: None,
+        }));
+
+        assert!(is_transient(
+            server_error
+                .step(|| "Unable to commit edit '42'".to_string())
+                .unwrap_err()
+                .as_ref()
+        ));
+        assert!(!is_transient(client_error.unwrap_err().as_ref()));
+        assert!(!is_transient(
+            Box::<dyn Error>::from("Bundle is not valid").as_ref()
+        ));
+    }
+}
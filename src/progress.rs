@@ -0,0 +1,99 @@
+use clap::ValueEnum;
+use std::io::Write;
+
+const BAR_WIDTH: u64 = 30;
+
+/// How upload progress is shown
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ProgressMode {
+    /// A bar on a terminal, otherwise plain lines
+    Auto,
+    /// A bar redrawn in place on standard error
+    Bar,
+    /// A line every few seconds, which suits CI logs
+    Plain,
+    /// No progress until the upload finishes
+    None,
+}
+
+impl ProgressMode {
+    /// The mode to use for `auto`, given whether standard error is a terminal. Timestamped
+    /// and logged output want whole lines, so never get a bar.
+    pub(crate) fn resolve(self, is_terminal: bool, wants_lines: bool) -> ProgressMode {
+        match self {
+            ProgressMode::Auto if is_terminal && !wants_lines => ProgressMode::Bar,
+            ProgressMode::Auto => ProgressMode::Plain,
+            mode => mode,
+        }
+    }
+}
+
+/// Format a progress bar, e.g. `[=========>          ]  45% 12.34 MB/s`
+pub(crate) fn format_bar(sent: u64, total: u64, rate: &str) -> String {
+    let percent = if total > 0 {
+        sent.min(total) * 100 / total
+    } else {
+        100
+    };
+    let filled = (percent * BAR_WIDTH / 100) as usize;
+    let mut bar = "=".repeat(filled);
+
+    if filled < BAR_WIDTH as usize {
+        bar.push('>');
+    }
+
+    format!(
+        "[{:<width$}] {:>3}% {}",
+        bar,
+        percent,
+        rate,
+        width = BAR_WIDTH as usize
+    )
+}
+
+/// Redraw the progress bar over the last one
+pub(crate) fn draw_bar(bar: &str) {
+    let mut stderr = std::io::stderr();
+
+    // Trailing spaces clear what is left of a longer rate from the last draw
+    let _ = write!(stderr, "\r{}    ", bar);
+    let _ = stderr.flush();
+}
+
+/// Move past the progress bar so that the next line isn't written over it
+pub(crate) fn finish_bar() {
+    eprintln!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(ProgressMode::Auto.resolve(true, false), ProgressMode::Bar);
+        assert_eq!(
+            ProgressMode::Auto.resolve(false, false),
+            ProgressMode::Plain
+        );
+        assert_eq!(ProgressMode::Auto.resolve(true, true), ProgressMode::Plain);
+        assert_eq!(ProgressMode::Bar.resolve(false, true), ProgressMode::Bar);
+        assert_eq!(ProgressMode::None.resolve(true, false), ProgressMode::None);
+    }
+
+    #[test]
+    fn test_format_bar() {
+        assert_eq!(
+            format_bar(0, 200, "0 B/s"),
+            "[>                             ]   0% 0 B/s"
+        );
+        assert_eq!(
+            format_bar(90, 200, "1.00 KB/s"),
+            "[=============>                ]  45% 1.00 KB/s"
+        );
+        assert_eq!(
+            format_bar(200, 200, "0 B/s"),
+            "[==============================] 100% 0 B/s"
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use crate::api_structs::ErrorResponse;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// How long to wait when the API rate limits us without saying for how long
+const DEFAULT_DELAY: Duration = Duration::from_secs(5);
+/// Never wait longer than this, however long the API asks for
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+// 403 reasons for short term limits. Daily quotas (`quotaExceeded`) won't reset soon enough
+// to be worth waiting for.
+const RATE_LIMIT_REASONS: &[&str] = &["rateLimitExceeded", "userRateLimitExceeded"];
+
+/// How long to wait before retrying a rate limited request, or `None` if the response is not
+/// a rate limit error. `retry_after` is the value of the `Retry-After` header in seconds.
+pub fn retry_delay(
+    status: StatusCode,
+    retry_after: Option<&str>,
+    error: Option<&ErrorResponse>,
+) -> Option<Duration> {
+    let rate_limited = status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN
+            && error.map_or(false, |error| {
+                error.error.errors.iter().any(|detail| {
+                    RATE_LIMIT_REASONS.contains(&detail.reason.as_deref().unwrap_or(""))
+                })
+            }));
+
+    if !rate_limited {
+        return None;
+    }
+
+    let delay = retry_after
+        .and_then(|retry_after| retry_after.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_DELAY);
+
+    Some(delay.min(MAX_DELAY))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let error: ErrorResponse = serde_json::from_str(
+            r#"{"error":{"code":403,"message":"Rate limit","errors":[{"reason":"userRateLimitExceeded"}]}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            retry_delay(StatusCode::TOO_MANY_REQUESTS, Some("12"), None),
+            Some(Duration::from_secs(12))
+        );
+        assert_eq!(
+            retry_delay(StatusCode::TOO_MANY_REQUESTS, Some("3600"), None),
+            Some(MAX_DELAY)
+        );
+        assert_eq!(
+            retry_delay(StatusCode::FORBIDDEN, None, Some(&error)),
+            Some(DEFAULT_DELAY)
+        );
+        assert_eq!(retry_delay(StatusCode::FORBIDDEN, None, None), None);
+        assert_eq!(retry_delay(StatusCode::NOT_FOUND, Some("1"), None), None);
+    }
+}
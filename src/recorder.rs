@@ -0,0 +1,145 @@
+use flate2::{write::GzEncoder, Compression};
+use serde::Serialize;
+use serde_json::Value;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+
+// Keys whose values are secrets wherever they appear in a request or response body
+const SECRET_KEYS: &[&str] = &[
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "private_key",
+    "private_key_id",
+    "client_secret",
+];
+const REDACTED: &str = "<redacted>";
+
+/// A request to the API and the response to it, as written to a recording
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Exchange {
+    pub method: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<Value>,
+    pub status: u16,
+    pub response_body: Value,
+}
+
+/// Collects the API requests and responses of a run so they can be attached to a bug report.
+/// Headers are never recorded, so bearer tokens are left out, and secrets are stripped from
+/// bodies.
+#[derive(Default)]
+pub struct Recorder {
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl Recorder {
+    /// Record an exchange. Bodies that are not JSON, such as uploaded bundles, are recorded
+    /// by their length only.
+    pub fn record(
+        &self,
+        method: &str,
+        url: &str,
+        request_body: Option<&[u8]>,
+        status: u16,
+        response_body: &[u8],
+    ) {
+        let exchange = Exchange {
+            method: method.to_string(),
+            url: url.to_string(),
+            request_body: request_body.map(sanitize_body),
+            status,
+            response_body: sanitize_body(response_body),
+        };
+
+        if let Ok(mut exchanges) = self.exchanges.lock() {
+            exchanges.push(exchange);
+        }
+    }
+
+    pub fn num_exchanges(&self) -> usize {
+        self.exchanges.lock().map_or(0, |exchanges| exchanges.len())
+    }
+
+    /// Write the exchanges as numbered JSON files in a `.tar.gz` archive
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let exchanges = self
+            .exchanges
+            .lock()
+            .map_err(|_| "Unable to read recorded API calls")?;
+        let mut archive = tar::Builder::new(GzEncoder::new(
+            File::create(path)
+                .map_err(|err| format!("Unable to create '{}': {}", path.display(), err))?,
+            Compression::default(),
+        ));
+
+        for (index, exchange) in exchanges.iter().enumerate() {
+            let content = serde_json::to_vec_pretty(exchange)?;
+            let mut header = tar::Header::new_gnu();
+
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive.append_data(
+                &mut header,
+                format!("gplay-record/{:04}.json", index + 1),
+                content.as_slice(),
+            )?;
+        }
+
+        archive.into_inner()?.finish()?;
+
+        Ok(())
+    }
+}
+
+fn sanitize_body(body: &[u8]) -> Value {
+    if body.is_empty() {
+        return Value::Null;
+    }
+
+    match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value
+        }
+        Err(_) => Value::String(format!("<{} bytes>", body.len())),
+    }
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sanitize_body() {
+        assert_eq!(
+            sanitize_body(
+                br#"{"id":"42","nested":[{"private_key":"-----BEGIN"}],"access_token":"ya29"}"#
+            ),
+            json!({ "id": "42", "nested": [{ "private_key": REDACTED }], "access_token": REDACTED })
+        );
+        assert_eq!(sanitize_body(b"PK\x03\x04"), json!("<4 bytes>"));
+        assert_eq!(sanitize_body(b""), Value::Null);
+    }
+}
@@ -0,0 +1,357 @@
+use crate::credentials::CredentialsProvider;
+use crate::GplayTool;
+use futures_util::future::LocalBoxFuture;
+use gcp_auth::Token;
+use reqwest::{Client, StatusCode, Url};
+use serde::Deserialize;
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// Somewhere gplay keeps state between runs, such as cached API responses. Keys are relative
+/// paths like `responses/com.example.app/tracks.json`.
+pub trait StateStore {
+    /// The value stored under a key, or `None` if there isn't one
+    fn get<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Vec<u8>>, Box<dyn Error>>>;
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+    ) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>>;
+    /// Remove every value stored under a directory of keys, e.g. `responses/com.example.app`
+    fn remove_all<'a>(&'a self, dir: &'a str) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>>;
+}
+
+/// Where to keep state, as given with `--state-store`
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateLocation {
+    Dir(PathBuf),
+    Gcs { bucket: String, prefix: String },
+}
+
+/// Parse a state location, which is either a directory or a `gs://bucket/prefix` URL
+pub fn parse_state_location(s: &str) -> Result<StateLocation, String> {
+    match s.strip_prefix("gs://") {
+        Some(path) => {
+            let (bucket, prefix) = path.split_once('/').unwrap_or((path, ""));
+
+            if bucket.is_empty() {
+                return Err(format!("'{}' has no bucket name", s));
+            }
+
+            Ok(StateLocation::Gcs {
+                bucket: bucket.to_string(),
+                prefix: prefix.trim_end_matches('/').to_string(),
+            })
+        }
+        None => Ok(StateLocation::Dir(PathBuf::from(s))),
+    }
+}
+
+/// Keeps state in a local directory
+pub struct LocalStore {
+    dir: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(dir: PathBuf) -> LocalStore {
+        LocalStore { dir }
+    }
+
+    /// A store in the user cache directory, where gplay keeps its state by default
+    pub fn user_cache() -> Option<LocalStore> {
+        let dir = match std::env::var_os("XDG_CACHE_HOME") {
+            Some(dir) => PathBuf::from(dir),
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+        };
+
+        Some(LocalStore::new(dir.join("gplay")))
+    }
+}
+
+impl StateStore for LocalStore {
+    fn get<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Vec<u8>>, Box<dyn Error>>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.dir.join(key)).await {
+                Ok(value) => Ok(Some(value)),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err.into()),
+            }
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+    ) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>> {
+        Box::pin(async move {
+            let path = self.dir.join(key);
+
+            if let Some(dir) = path.parent() {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+
+            Ok(tokio::fs::write(path, value).await?)
+        })
+    }
+
+    fn remove_all<'a>(&'a self, dir: &'a str) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>> {
+        Box::pin(async move {
+            match tokio::fs::remove_dir_all(self.dir.join(dir)).await {
+                Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectList {
+    #[serde(default)]
+    items: Vec<Object>,
+    next_page_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Object {
+    name: String,
+}
+
+/// Keeps state in a Google Cloud Storage bucket, so that it is shared by CI runners that
+/// don't keep their disks between jobs
+pub struct GcsStore {
+    client: Client,
+    token: Token,
+    bucket: String,
+    prefix: String,
+}
+
+impl GcsStore {
+    pub const SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+    const STORAGE_URL: &str = "https://storage.googleapis.com/storage/v1/b";
+    const UPLOAD_URL: &str = "https://storage.googleapis.com/upload/storage/v1/b";
+
+    /// The token must have the `devstorage.read_write` scope
+    pub fn new(client: Client, token: Token, bucket: &str, prefix: &str) -> GcsStore {
+        GcsStore {
+            client,
+            token,
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    fn object_name(&self, key: &str) -> String {
+        if self.prefix.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}/{}", self.prefix, key)
+        }
+    }
+
+    // The URL of an object, whose name is a single path segment even though it contains slashes
+    fn object_url(&self, name: &str) -> Result<Url, Box<dyn Error>> {
+        let mut url = Url::parse(Self::STORAGE_URL)?;
+
+        url.path_segments_mut()
+            .map_err(|_| "Invalid storage URL")?
+            .extend([self.bucket.as_str(), "o", name]);
+
+        Ok(url)
+    }
+
+    fn check_status(status: StatusCode, action: &str) -> Result<(), Box<dyn Error>> {
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Unable to {} state in the storage bucket ({})",
+                action, status
+            )
+            .into())
+        }
+    }
+}
+
+impl StateStore for GcsStore {
+    fn get<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Vec<u8>>, Box<dyn Error>>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .get(self.object_url(&self.object_name(key))?)
+                .query(&[("alt", "media")])
+                .bearer_auth(self.token.as_str())
+                .send()
+                .await?;
+
+            if response.status() == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+
+            Self::check_status(response.status(), "read")?;
+
+            Ok(Some(response.bytes().await?.to_vec()))
+        })
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        value: Vec<u8>,
+    ) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(format!("{}/{}/o", Self::UPLOAD_URL, self.bucket))
+                .query(&[
+                    ("uploadType", "media"),
+                    ("name", self.object_name(key).as_str()),
+                ])
+                .bearer_auth(self.token.as_str())
+                .header("Content-Type", "application/json")
+                .body(value)
+                .send()
+                .await?;
+
+            Self::check_status(response.status(), "write")
+        })
+    }
+
+    fn remove_all<'a>(&'a self, dir: &'a str) -> LocalBoxFuture<'a, Result<(), Box<dyn Error>>> {
+        Box::pin(async move {
+            let prefix = format!("{}/", self.object_name(dir));
+            let mut page_token: Option<String> = None;
+
+            loop {
+                let mut request = self
+                    .client
+                    .get(format!("{}/{}/o", Self::STORAGE_URL, self.bucket))
+                    .query(&[
+                        ("prefix", prefix.as_str()),
+                        ("fields", "items(name),nextPageToken"),
+                    ])
+                    .bearer_auth(self.token.as_str());
+
+                if let Some(page_token) = &page_token {
+                    request = request.query(&[("pageToken", page_token)]);
+                }
+
+                let response = request.send().await?;
+
+                Self::check_status(response.status(), "list")?;
+
+                let object_list = response.json::<ObjectList>().await?;
+
+                for object in object_list.items.iter() {
+                    let response = self
+                        .client
+                        .delete(self.object_url(&object.name)?)
+                        .bearer_auth(self.token.as_str())
+                        .send()
+                        .await?;
+
+                    // Another run may have removed it first
+                    if response.status() != StatusCode::NOT_FOUND {
+                        Self::check_status(response.status(), "remove")?;
+                    }
+                }
+
+                page_token = object_list.next_page_token;
+
+                if page_token.is_none() {
+                    return Ok(());
+                }
+            }
+        })
+    }
+}
+
+impl GplayTool<'_> {
+    /// Open the store given with `--state-store`, or the user cache directory by default. A
+    /// bucket is accessed with the default credentials.
+    pub(crate) async fn open_state_store(
+        &self,
+        location: Option<&StateLocation>,
+        credentials: Option<&dyn CredentialsProvider>,
+        client: &Client,
+    ) -> Result<Option<Box<dyn StateStore>>, Box<dyn Error>> {
+        let store: Box<dyn StateStore> = match location {
+            Some(StateLocation::Dir(dir)) => Box::new(LocalStore::new(dir.clone())),
+            Some(StateLocation::Gcs { bucket, prefix }) => {
+                let credentials = credentials
+                    .ok_or("A credentials file must be given with --cred-file to use a bucket")?;
+                let token = self.authenticate(credentials, &[GcsStore::SCOPE]).await?;
+
+                Box::new(GcsStore::new(client.clone(), token, bucket, prefix))
+            }
+            None => match LocalStore::user_cache() {
+                Some(store) => Box::new(store),
+                None => return Ok(None),
+            },
+        };
+
+        Ok(Some(store))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_state_location() {
+        assert_eq!(
+            parse_state_location("gs://ci-state/gplay/").unwrap(),
+            StateLocation::Gcs {
+                bucket: "ci-state".to_string(),
+                prefix: "gplay".to_string(),
+            }
+        );
+        assert_eq!(
+            parse_state_location("gs://ci-state").unwrap(),
+            StateLocation::Gcs {
+                bucket: "ci-state".to_string(),
+                prefix: "".to_string(),
+            }
+        );
+        assert_eq!(
+            parse_state_location("/tmp/gplay").unwrap(),
+            StateLocation::Dir(PathBuf::from("/tmp/gplay"))
+        );
+        assert!(parse_state_location("gs:///gplay").is_err());
+    }
+
+    #[test]
+    fn test_local_store() {
+        let dir = std::env::temp_dir().join(format!("gplay-state-{}", std::process::id()));
+        let store = LocalStore::new(dir.clone());
+
+        tokio_test::block_on(async {
+            assert_eq!(store.get("responses/app/tracks.json").await.unwrap(), None);
+            store
+                .put("responses/app/tracks.json", b"{}".to_vec())
+                .await
+                .unwrap();
+            assert_eq!(
+                store.get("responses/app/tracks.json").await.unwrap(),
+                Some(b"{}".to_vec())
+            );
+            store.remove_all("responses/app").await.unwrap();
+            assert_eq!(store.get("responses/app/tracks.json").await.unwrap(), None);
+            store.remove_all("responses/app").await.unwrap();
+        });
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
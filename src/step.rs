@@ -0,0 +1,60 @@
+use std::error::Error;
+use std::fmt;
+
+/// An error annotated with the step that failed, keeping the original error as its source
+#[derive(Debug)]
+pub struct StepError {
+    step: String,
+    source: Box<dyn Error>,
+}
+
+impl StepError {
+    /// The description of the step and the error that made it fail
+    pub(crate) fn into_parts(self) -> (String, Box<dyn Error>) {
+        (self.step, self.source)
+    }
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.step)
+    }
+}
+
+impl Error for StepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+pub trait StepContext<T> {
+    /// Describe the step that produced this result if it failed
+    fn step<F: FnOnce() -> String>(self, describe: F) -> Result<T, Box<dyn Error>>;
+}
+
+impl<T, E: Into<Box<dyn Error>>> StepContext<T> for Result<T, E> {
+    fn step<F: FnOnce() -> String>(self, describe: F) -> Result<T, Box<dyn Error>> {
+        self.map_err(|err| {
+            Box::new(StepError {
+                step: describe(),
+                source: err.into(),
+            }) as Box<dyn Error>
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step() {
+        let result: Result<(), Box<dyn Error>> = Err("403 Forbidden".into());
+        let err = result
+            .step(|| "Unable to commit edit '42'".to_string())
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "Unable to commit edit '42'");
+        assert_eq!(err.source().unwrap().to_string(), "403 Forbidden");
+    }
+}
@@ -0,0 +1,69 @@
+use serde_json::Value;
+
+/// Find the paths of fields present in a raw API response that were dropped when it was
+/// deserialized into our models, given the `known` re-serialized form of those models.
+pub fn unknown_fields(raw: &Value, known: &Value) -> Vec<String> {
+    let mut fields = vec![];
+
+    collect_unknown_fields(raw, known, "", &mut fields);
+
+    fields
+}
+
+fn collect_unknown_fields(raw: &Value, known: &Value, path: &str, fields: &mut Vec<String>) {
+    match (raw, known) {
+        (Value::Object(raw_map), Value::Object(known_map)) => {
+            for (key, raw_value) in raw_map.iter() {
+                let field_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+
+                match known_map.get(key) {
+                    Some(known_value) => {
+                        collect_unknown_fields(raw_value, known_value, &field_path, fields)
+                    }
+                    // Optional fields that are null are not serialized back out
+                    None if raw_value.is_null() => {}
+                    None => fields.push(field_path),
+                }
+            }
+        }
+        (Value::Array(raw_items), Value::Array(known_items)) => {
+            for (index, (raw_item, known_item)) in raw_items.iter().zip(known_items).enumerate() {
+                collect_unknown_fields(
+                    raw_item,
+                    known_item,
+                    &format!("{}[{}]", path, index),
+                    fields,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_unknown_fields() {
+        let raw = json!({
+            "track": "beta",
+            "countryAvailability": {},
+            "releases": [{ "status": "draft", "versionCodes": null, "newField": 1 }]
+        });
+        let known = json!({
+            "track": "beta",
+            "releases": [{ "status": "draft" }]
+        });
+
+        assert_eq!(
+            unknown_fields(&raw, &known),
+            vec!["countryAvailability", "releases[0].newField"]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::error::Error;
+
+/// Rows of text output with named columns, rendered with aligned columns
+pub struct Table {
+    columns: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(columns: &[&'static str]) -> Table {
+        Table {
+            columns: columns.to_vec(),
+            rows: vec![],
+        }
+    }
+
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// Render the table as lines of text, with only the `selected` columns in the given order
+    /// or all of them if none are selected
+    pub fn render(&self, selected: &[String], header: bool) -> Result<Vec<String>, Box<dyn Error>> {
+        let indexes = if selected.is_empty() {
+            (0..self.columns.len()).collect()
+        } else {
+            selected
+                .iter()
+                .map(|name| {
+                    self.columns
+                        .iter()
+                        .position(|column| column == name)
+                        .ok_or_else(|| {
+                            format!(
+                                "Unknown column '{}', expected one of {}",
+                                name,
+                                self.columns.join(", ")
+                            )
+                        })
+                })
+                .collect::<Result<Vec<usize>, String>>()?
+        };
+        let header_row: Vec<String> = self.columns.iter().map(|s| s.to_string()).collect();
+        let rows: Vec<&Vec<String>> = header
+            .then_some(&header_row)
+            .into_iter()
+            .chain(self.rows.iter())
+            .collect();
+        let widths: Vec<usize> = indexes
+            .iter()
+            .map(|index| {
+                rows.iter()
+                    .map(|row| row[*index].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                indexes
+                    .iter()
+                    .zip(widths.iter())
+                    .map(|(index, width)| format!("{:width$}", row[*index], width = width))
+                    .collect::<Vec<String>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut table = Table::new(&["versionCode", "tracks"]);
+
+        table.add_row(vec!["9".to_string(), "beta".to_string()]);
+        table.add_row(vec!["10".to_string(), "".to_string()]);
+
+        assert_eq!(
+            table.render(&[], true).unwrap(),
+            vec!["versionCode  tracks", "9            beta", "10"]
+        );
+        assert_eq!(
+            table.render(&["tracks".to_string()], false).unwrap(),
+            vec!["beta", ""]
+        );
+        assert!(table.render(&["sha1".to_string()], true).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use bytes::BytesMut;
+use reqwest::Body;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Parse a byte rate such as `10MBps`, `512KB/s` or `1000000` into bytes per second
+pub fn parse_byte_rate(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let s = s
+        .strip_suffix("ps")
+        .or_else(|| s.strip_suffix("/s"))
+        .unwrap_or(s);
+    let split_at = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split_at);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a valid byte rate", s))?;
+    let multiplier = match unit.to_ascii_uppercase().as_str() {
+        "" | "B" => 1.0,
+        "KB" => 1_000.0,
+        "MB" => 1_000_000.0,
+        "GB" => 1_000_000_000.0,
+        "KIB" => 1_024.0,
+        "MIB" => 1_048_576.0,
+        "GIB" => 1_073_741_824.0,
+        _ => return Err(format!("Unknown byte rate unit '{}'", unit)),
+    };
+    let rate = (number * multiplier) as u64;
+
+    if rate == 0 {
+        return Err("Byte rate must be greater than zero".to_string());
+    }
+
+    Ok(rate)
+}
+
+/// Format a byte rate for display, e.g. `12.34 MB/s`
+pub fn format_byte_rate(bytes: u64, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64();
+    let rate = if secs > 0.0 { bytes as f64 / secs } else { 0.0 };
+
+    if rate >= 1_000_000.0 {
+        format!("{:.2} MB/s", rate / 1_000_000.0)
+    } else if rate >= 1_000.0 {
+        format!("{:.2} KB/s", rate / 1_000.0)
+    } else {
+        format!("{:.0} B/s", rate)
+    }
+}
+
+/// Create a request body that streams from `reader` in chunks, counting the bytes sent in
+/// `bytes_sent` and sleeping as needed to stay under `max_rate` bytes per second. The counter
+/// may already hold the bytes sent by earlier bodies, e.g. chunks of the same upload.
+pub fn throttled_body<R>(reader: R, max_rate: Option<u64>, bytes_sent: Arc<AtomicU64>) -> Body
+where
+    R: AsyncRead + Send + Sync + Unpin + 'static,
+{
+    let initial_bytes_sent = bytes_sent.load(Ordering::Relaxed);
+    let stream =
+        futures_util::stream::unfold((Some(reader), Instant::now()), move |(reader, start)| {
+            let bytes_sent = bytes_sent.clone();
+
+            async move {
+                let mut reader = reader?;
+
+                if let Some(max_rate) = max_rate {
+                    let sent = bytes_sent
+                        .load(Ordering::Relaxed)
+                        .saturating_sub(initial_bytes_sent);
+                    let due = Duration::from_secs_f64(sent as f64 / max_rate as f64);
+                    let elapsed = start.elapsed();
+
+                    if due > elapsed {
+                        tokio::time::sleep(due - elapsed).await;
+                    }
+                }
+
+                let mut chunk = BytesMut::with_capacity(CHUNK_SIZE);
+
+                match reader.read_buf(&mut chunk).await {
+                    Ok(0) => None,
+                    Ok(num_bytes) => {
+                        bytes_sent.fetch_add(num_bytes as u64, Ordering::Relaxed);
+
+                        Some((Ok(chunk.freeze()), (Some(reader), start)))
+                    }
+                    // Nothing more is read after an error, which fails the upload
+                    Err(err) => Some((Err(err), (None, start))),
+                }
+            }
+        });
+
+    Body::wrap_stream(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_rate() {
+        assert_eq!(parse_byte_rate("10MBps"), Ok(10_000_000));
+        assert_eq!(parse_byte_rate("512KB/s"), Ok(512_000));
+        assert_eq!(parse_byte_rate("1.5MiB"), Ok(1_572_864));
+        assert_eq!(parse_byte_rate("2048"), Ok(2048));
+        assert!(parse_byte_rate("fast").is_err());
+        assert!(parse_byte_rate("10XB").is_err());
+        assert!(parse_byte_rate("0").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use crate::GplayLog;
+use core::fmt::Arguments;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A log that prefixes every line written to another log with an ISO-8601 UTC timestamp
+pub struct TimestampLog<'a> {
+    inner: &'a dyn GplayLog,
+}
+
+impl<'a> TimestampLog<'a> {
+    pub fn new(inner: &'a dyn GplayLog) -> TimestampLog<'a> {
+        TimestampLog { inner }
+    }
+}
+
+impl GplayLog for TimestampLog<'_> {
+    fn output(self: &Self, args: Arguments) {
+        self.inner.output(format_args!(
+            "{} {}",
+            format_timestamp(SystemTime::now()),
+            args
+        ));
+    }
+    fn warning(self: &Self, args: Arguments) {
+        self.inner.warning(format_args!(
+            "{} {}",
+            format_timestamp(SystemTime::now()),
+            args
+        ));
+    }
+    fn error(self: &Self, args: Arguments) {
+        self.inner.error(format_args!(
+            "{} {}",
+            format_timestamp(SystemTime::now()),
+            args
+        ));
+    }
+}
+
+/// Format a time as e.g. `2023-10-14T09:30:05.123Z`
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs_of_day = secs % 86_400;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// Convert days since 1970-01-01 to a proleptic Gregorian (year, month, day), see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_millis(1_697_275_805_123)),
+            "2023-10-14T09:30:05.123Z"
+        );
+        assert_eq!(
+            format_timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)),
+            "2000-02-29T00:00:00.000Z"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::api_structs::TracksList;
+use serde_json::Value;
+
+const TOP_LEVEL_KEYS: &[&str] = &["tracks"];
+const TRACK_KEYS: &[&str] = &["track", "releases"];
+const RELEASE_KEYS: &[&str] = &[
+    "name",
+    "status",
+    "versionCodes",
+    "releaseNotes",
+    "userFraction",
+    "countryTargeting",
+    "inAppUpdatePriority",
+];
+const RELEASE_NOTE_KEYS: &[&str] = &["language", "text"];
+const COUNTRY_TARGETING_KEYS: &[&str] = &["countries", "includeRestOfWorld"];
+
+/// Parse a tracks YAML file, reporting every unknown or missing key by its path before
+/// reporting type errors with their line and column.
+pub fn parse(content: &str) -> Result<TracksList, Vec<String>> {
+    let yaml: serde_yaml::Value =
+        serde_yaml::from_str(content).map_err(|err| vec![err.to_string()])?;
+    let value = serde_json::to_value(yaml).map_err(|err| vec![err.to_string()])?;
+    let mut errors = vec![];
+
+    check_object(&value, "", TOP_LEVEL_KEYS, &["tracks"], &mut errors);
+
+    for (track_index, track) in items(&value, "tracks").iter().enumerate() {
+        let track_path = format!("tracks[{}]", track_index);
+
+        check_object(track, &track_path, TRACK_KEYS, &["track"], &mut errors);
+
+        for (release_index, release) in items(track, "releases").iter().enumerate() {
+            let release_path = format!("{}.releases[{}]", track_path, release_index);
+
+            check_object(
+                release,
+                &release_path,
+                RELEASE_KEYS,
+                &["status"],
+                &mut errors,
+            );
+
+            for (note_index, note) in items(release, "releaseNotes").iter().enumerate() {
+                check_object(
+                    note,
+                    &format!("{}.releaseNotes[{}]", release_path, note_index),
+                    RELEASE_NOTE_KEYS,
+                    RELEASE_NOTE_KEYS,
+                    &mut errors,
+                );
+            }
+
+            if let Some(country_targeting) = release.get("countryTargeting") {
+                check_object(
+                    country_targeting,
+                    &format!("{}.countryTargeting", release_path),
+                    COUNTRY_TARGETING_KEYS,
+                    &[],
+                    &mut errors,
+                );
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    serde_yaml::from_str::<TracksList>(content).map_err(|err| vec![err.to_string()])
+}
+
+fn items<'v>(value: &'v Value, key: &str) -> &'v [Value] {
+    value
+        .get(key)
+        .and_then(|items| items.as_array())
+        .map(|items| items.as_slice())
+        .unwrap_or_default()
+}
+
+fn check_object(
+    value: &Value,
+    path: &str,
+    known_keys: &[&str],
+    required_keys: &[&str],
+    errors: &mut Vec<String>,
+) {
+    let location = if path.is_empty() { "top level" } else { path };
+    let map = match value.as_object() {
+        Some(map) => map,
+        None => {
+            errors.push(format!("{}: expected a mapping", location));
+            return;
+        }
+    };
+
+    for key in map.keys() {
+        if !known_keys.contains(&key.as_str()) {
+            let mut message = format!("{}: unknown key '{}'", location, key);
+
+            if let Some(suggestion) = closest_key(key, known_keys) {
+                message.push_str(&format!(", did you mean '{}'?", suggestion));
+            }
+
+            errors.push(message);
+        }
+    }
+
+    for key in required_keys.iter() {
+        if !map.contains_key(*key) {
+            errors.push(format!("{}: missing required key '{}'", location, key));
+        }
+    }
+}
+
+// Suggest a known key when the unknown one is probably a typo
+fn closest_key<'k>(key: &str, known_keys: &[&'k str]) -> Option<&'k str> {
+    known_keys
+        .iter()
+        .map(|known_key| (edit_distance(key, known_key), *known_key))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known_key)| known_key)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+
+        for (j, b_char) in b.iter().enumerate() {
+            let cost = if a_char == *b_char { 0 } else { 1 };
+
+            current.push(
+                (previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1),
+            );
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let tracks_list = parse(
+            "tracks:\n  - track: beta\n    releases:\n      - status: completed\n        versionCodes: ['12']\n",
+        )
+        .unwrap();
+
+        assert_eq!(tracks_list.tracks[0].name, "beta");
+
+        let errors =
+            parse("tracks:\n  - releases:\n      - status: completed\n        userFraktion: 0.1\n")
+                .unwrap_err();
+
+        assert_eq!(
+            errors,
+            vec![
+                "tracks[0]: missing required key 'track'",
+                "tracks[0].releases[0]: unknown key 'userFraktion', did you mean 'userFraction'?",
+            ]
+        );
+
+        let errors = parse("tracks:\n  - track: beta\n    releases: 3\n").unwrap_err();
+
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("line 3"));
+    }
+}
@@ -0,0 +1,588 @@
+use crate::api::{AndroidPublisherApi, ArtifactKind, UploadProgress};
+use crate::api_structs::{Apk, Bundle, DeobfuscationFilesUploadResponse};
+use crate::bundle_file::BundleFile;
+use crate::{error, pipeline, throughput, warning, GplayError, GplayLog};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::io::Cursor;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+/// The resumable upload protocol requires every chunk but the last to be a multiple of this
+pub const CHUNK_GRANULARITY: u64 = 256 * 1024;
+pub const DEFAULT_CHUNK_SIZE: u64 = 32 * CHUNK_GRANULARITY;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+const MAX_CHUNK_RETRIES: usize = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Parse an upload chunk size such as `8MiB`, which must be a multiple of 256KiB
+pub fn parse_chunk_size(s: &str) -> Result<u64, String> {
+    let chunk_size =
+        throughput::parse_byte_rate(s).map_err(|_| format!("'{}' is not a valid size", s))?;
+
+    if chunk_size % CHUNK_GRANULARITY != 0 {
+        return Err(format!(
+            "Chunk size must be a multiple of 256KiB, e.g. {}MiB",
+            (chunk_size / CHUNK_GRANULARITY / 4).max(1)
+        ));
+    }
+
+    Ok(chunk_size)
+}
+
+// Whether a chunk failed in a way that resuming could get past, i.e. the request didn't get
+// through or the server failed, rather than the upload being rejected
+fn is_resumable(error: &GplayError) -> bool {
+    pipeline::is_transient(error) || matches!(error, GplayError::Http(_))
+}
+
+/// The hashes that Google Play reports for an uploaded artifact
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ReportedHashes<'a> {
+    pub sha1: Option<&'a str>,
+    pub sha256: Option<&'a str>,
+}
+
+/// The response to an upload, which may report hashes of what was received
+pub trait UploadedArtifact: for<'de> Deserialize<'de> + Serialize {
+    fn reported_hashes(&self) -> ReportedHashes<'_> {
+        ReportedHashes::default()
+    }
+}
+
+impl UploadedArtifact for Bundle {
+    fn reported_hashes(&self) -> ReportedHashes<'_> {
+        ReportedHashes {
+            sha1: self.sha1.as_deref(),
+            sha256: self.sha256.as_deref(),
+        }
+    }
+}
+
+impl UploadedArtifact for Apk {
+    fn reported_hashes(&self) -> ReportedHashes<'_> {
+        self.binary
+            .as_ref()
+            .map_or(ReportedHashes::default(), |binary| ReportedHashes {
+                sha1: binary.sha1.as_deref(),
+                sha256: binary.sha256.as_deref(),
+            })
+    }
+}
+
+impl UploadedArtifact for DeobfuscationFilesUploadResponse {}
+
+/// A hash for checking that what Google Play received is what was sent
+pub trait ArtifactHash {
+    /// The name of the hash for messages, e.g. `SHA-256`
+    fn name(&self) -> &'static str;
+    fn update(&mut self, bytes: &[u8]);
+    /// The lowercase hex digest of everything hashed since the last one
+    fn finish(&mut self) -> String;
+    /// The digest of this kind that Google Play reported, if any
+    fn reported<'h>(&self, hashes: &ReportedHashes<'h>) -> Option<&'h str>;
+}
+
+#[derive(Default)]
+pub struct Sha256Hash(Sha256);
+
+impl ArtifactHash for Sha256Hash {
+    fn name(&self) -> &'static str {
+        "SHA-256"
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&mut self) -> String {
+        self.0
+            .finalize_reset()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    fn reported<'h>(&self, hashes: &ReportedHashes<'h>) -> Option<&'h str> {
+        hashes.sha256
+    }
+}
+
+// Hash the artifact that was uploaded and compare it with the hash Google Play reported.
+// Responses that don't report this kind of hash can't be checked.
+async fn check_hash(
+    hash: &mut dyn ArtifactHash,
+    source: &BundleFile,
+    chunk_size: u64,
+    reported: ReportedHashes<'_>,
+) -> Result<(), Box<dyn Error>> {
+    let expected = match hash.reported(&reported) {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let mut offset = 0;
+
+    while offset < source.size() {
+        let chunk_len = chunk_size.min(source.size() - offset);
+
+        hash.update(&source.read_chunk(offset, chunk_len).await?);
+        offset += chunk_len;
+    }
+
+    let local = hash.finish();
+
+    if !local.eq_ignore_ascii_case(expected) {
+        return Err(format!(
+            "The {} of the upload, {}, does not match the local file's {}",
+            hash.name(),
+            expected,
+            local
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Uploads bundles, APKs and deobfuscation files to an edit. Artifacts larger than the chunk
+/// size are sent with the resumable upload protocol, resuming from the last byte the server
+/// has when a chunk fails, and smaller ones in a single request.
+pub struct ArtifactUploader<'a> {
+    api: AndroidPublisherApi<'a>,
+    log: &'a dyn GplayLog,
+    kind: ArtifactKind,
+    content_type: Option<String>,
+    chunk_size: u64,
+    timeout: Duration,
+    max_rate: Option<u64>,
+    bytes_sent: Arc<AtomicU64>,
+    hash: Option<Box<dyn ArtifactHash + 'a>>,
+}
+
+impl<'a> ArtifactUploader<'a> {
+    pub fn new(
+        api: AndroidPublisherApi<'a>,
+        log: &'a dyn GplayLog,
+        kind: ArtifactKind,
+    ) -> ArtifactUploader<'a> {
+        ArtifactUploader {
+            api,
+            log,
+            kind,
+            content_type: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            timeout: DEFAULT_TIMEOUT,
+            max_rate: None,
+            bytes_sent: Arc::new(AtomicU64::new(0)),
+            hash: None,
+        }
+    }
+
+    /// Send the artifact as this content type instead of the one for its kind
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Must be a multiple of [`CHUNK_GRANULARITY`]
+    pub fn chunk_size(mut self, chunk_size: u64) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// The timeout for each request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Limit the upload to this many bytes per second
+    pub fn max_rate(mut self, max_rate: Option<u64>) -> Self {
+        self.max_rate = max_rate;
+        self
+    }
+
+    /// Count the bytes that the server has received in `bytes_sent`, e.g. to report progress
+    /// while the upload runs
+    pub fn progress(mut self, bytes_sent: Arc<AtomicU64>) -> Self {
+        self.bytes_sent = bytes_sent;
+        self
+    }
+
+    /// Check the upload against the hash that Google Play reports for it
+    pub fn verify(mut self, hash: impl ArtifactHash + 'a) -> Self {
+        self.hash = Some(Box::new(hash));
+        self
+    }
+
+    pub async fn upload<T: UploadedArtifact>(
+        &mut self,
+        package_name: &str,
+        edit_id: &str,
+        source: &BundleFile,
+    ) -> Result<T, GplayError> {
+        let uploaded = self.send::<T>(package_name, edit_id, source).await?;
+
+        if let Some(hash) = self.hash.as_mut() {
+            check_hash(
+                hash.as_mut(),
+                source,
+                self.chunk_size,
+                uploaded.reported_hashes(),
+            )
+            .await?;
+        }
+
+        Ok(uploaded)
+    }
+
+    async fn send<T: UploadedArtifact>(
+        &self,
+        package_name: &str,
+        edit_id: &str,
+        source: &BundleFile,
+    ) -> Result<T, Box<dyn Error>> {
+        let content_type = self
+            .content_type
+            .as_deref()
+            .unwrap_or(self.kind.content_type());
+        let num_bytes = source.size();
+
+        if num_bytes <= self.chunk_size {
+            return Ok(self
+                .api
+                .upload_artifact(
+                    package_name,
+                    edit_id,
+                    &self.kind,
+                    content_type,
+                    throughput::throttled_body(
+                        source.reader().await?,
+                        self.max_rate,
+                        self.bytes_sent.clone(),
+                    ),
+                    num_bytes,
+                    self.timeout,
+                )
+                .await?);
+        }
+
+        let session_url = self
+            .api
+            .start_artifact_upload(package_name, edit_id, &self.kind, content_type, num_bytes)
+            .await?;
+
+        self.send_chunks(&session_url, source).await
+    }
+
+    // Send the artifact to a resumable upload session a chunk at a time
+    async fn send_chunks<T: UploadedArtifact>(
+        &self,
+        session_url: &str,
+        source: &BundleFile,
+    ) -> Result<T, Box<dyn Error>> {
+        let num_bytes = source.size();
+        let mut offset = 0;
+        let mut num_retries = 0;
+
+        loop {
+            let chunk_len = self.chunk_size.min(num_bytes - offset);
+            let chunk = source.read_chunk(offset, chunk_len).await?;
+            let sent = self
+                .api
+                .upload_chunk(
+                    session_url,
+                    throughput::throttled_body(
+                        Cursor::new(chunk),
+                        self.max_rate,
+                        self.bytes_sent.clone(),
+                    ),
+                    offset,
+                    chunk_len,
+                    num_bytes,
+                    self.timeout,
+                )
+                .await;
+            let progress = match sent {
+                Ok(progress) => {
+                    num_retries = 0;
+                    progress
+                }
+                Err(err) if num_retries < MAX_CHUNK_RETRIES && is_resumable(&err) => {
+                    num_retries += 1;
+                    warning!(
+                        self.log,
+                        "Upload of bytes {}-{} failed, resuming ({} of {}): {}",
+                        offset,
+                        offset + chunk_len - 1,
+                        num_retries,
+                        MAX_CHUNK_RETRIES,
+                        error::describe(&err)
+                    );
+                    tokio::time::sleep(RETRY_DELAY * num_retries as u32).await;
+                    self.api.query_upload(session_url, num_bytes).await?
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            match progress {
+                UploadProgress::Complete(uploaded) => return Ok(uploaded),
+                UploadProgress::Received(received) => {
+                    offset = resume_offset(received, num_bytes, &self.bytes_sent)?;
+                }
+            }
+        }
+    }
+}
+
+// Where to send the next chunk from when the server has received `received` bytes. After a
+// chunk fails part way through this is behind the bytes that were counted as sent, so the
+// count goes back down to what the server actually has.
+fn resume_offset(
+    received: u64,
+    num_bytes: u64,
+    bytes_sent: &AtomicU64,
+) -> Result<u64, Box<dyn Error>> {
+    if received >= num_bytes {
+        return Err(format!(
+            "All {} bytes were received but the upload did not complete",
+            num_bytes
+        )
+        .into());
+    }
+
+    bytes_sent.store(received, Ordering::Relaxed);
+
+    Ok(received)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_structs::ApkBinary;
+    use bytes::Bytes;
+    use core::fmt::Arguments;
+    use gcp_auth::Token;
+    use reqwest::Client;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{TcpListener, TcpStream};
+
+    struct TestLogger;
+
+    impl GplayLog for TestLogger {
+        fn output(&self, _args: Arguments) {}
+        fn warning(&self, _args: Arguments) {}
+        fn error(&self, _args: Arguments) {}
+    }
+
+    // Accept a request to the mock upload server and read its headers, returning its
+    // `Content-Range` and `Content-Length`
+    async fn accept(listener: &TcpListener) -> (BufReader<TcpStream>, String, usize) {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut stream = BufReader::new(stream);
+        let mut content_range = String::new();
+        let mut content_length = 0;
+
+        loop {
+            let mut line = String::new();
+
+            if stream.read_line(&mut line).await.unwrap() == 0 || line.trim_end().is_empty() {
+                break;
+            }
+
+            if let Some((name, value)) = line.split_once(':') {
+                match name.to_ascii_lowercase().as_str() {
+                    "content-range" => content_range = value.trim().to_string(),
+                    "content-length" => content_length = value.trim().parse().unwrap(),
+                    _ => (),
+                }
+            }
+        }
+
+        (stream, content_range, content_length)
+    }
+
+    async fn read_body(stream: &mut BufReader<TcpStream>, content_length: usize) -> Vec<u8> {
+        let mut body = vec![0; content_length];
+
+        stream.read_exact(&mut body).await.unwrap();
+        body
+    }
+
+    async fn respond(stream: &mut BufReader<TcpStream>, status: &str, header: &str, body: &str) {
+        let response = format!(
+            "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            header,
+            body.len(),
+            body
+        );
+
+        stream.write_all(response.as_bytes()).await.unwrap();
+    }
+
+    #[test]
+    fn test_parse_chunk_size() {
+        assert_eq!(parse_chunk_size("8MiB"), Ok(DEFAULT_CHUNK_SIZE));
+        assert_eq!(parse_chunk_size("256KiB"), Ok(CHUNK_GRANULARITY));
+        assert!(parse_chunk_size("8MB").is_err());
+        assert!(parse_chunk_size("big").is_err());
+    }
+
+    #[test]
+    fn test_resume_offset() {
+        let num_bytes = 4 * CHUNK_GRANULARITY;
+        // Three chunks were counted as sent, but a 308 with `Range: bytes=0-262143` says the
+        // server only has the first
+        let bytes_sent = AtomicU64::new(3 * CHUNK_GRANULARITY);
+
+        assert_eq!(
+            resume_offset(CHUNK_GRANULARITY, num_bytes, &bytes_sent).unwrap(),
+            CHUNK_GRANULARITY
+        );
+        assert_eq!(bytes_sent.load(Ordering::Relaxed), CHUNK_GRANULARITY);
+        assert!(resume_offset(num_bytes, num_bytes, &bytes_sent).is_err());
+    }
+
+    #[test]
+    fn test_artifact_kind() {
+        let kind = ArtifactKind::DeobfuscationFile {
+            version_code: 42,
+            file_type: "nativeCode".to_string(),
+        };
+
+        assert_eq!(kind.path(), "apks/42/deobfuscationFiles/nativeCode");
+        assert_eq!(kind.to_string(), "native debug symbols");
+        assert_eq!(
+            ArtifactKind::Bundle {
+                ack_install_warning: true
+            }
+            .query(),
+            vec![("ackBundleInstallationWarning", "true".to_string())]
+        );
+        assert_eq!(
+            ArtifactKind::Apk.content_type(),
+            "application/vnd.android.package-archive"
+        );
+    }
+
+    #[test]
+    fn test_check_hash() {
+        const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let source = BundleFile::from_bytes(Bytes::from_static(b"abc"));
+        let apk = |sha256: &str| Apk {
+            version_code: 42,
+            binary: Some(ApkBinary {
+                sha1: None,
+                sha256: Some(sha256.to_string()),
+            }),
+        };
+        let mut hash = Sha256Hash::default();
+
+        tokio_test::block_on(async {
+            // A chunk size smaller than the source hashes it in pieces
+            check_hash(&mut hash, &source, 2, apk(ABC_SHA256).reported_hashes())
+                .await
+                .unwrap();
+            assert!(
+                check_hash(&mut hash, &source, 2, apk("0123").reported_hashes())
+                    .await
+                    .is_err()
+            );
+            check_hash(&mut hash, &source, 2, ReportedHashes::default())
+                .await
+                .unwrap();
+        });
+    }
+
+    #[test]
+    fn test_send_chunks_resumes() {
+        let num_bytes = 3 * CHUNK_GRANULARITY / 2;
+        let data: Vec<u8> = (0..num_bytes).map(|i| (i % 251) as u8).collect();
+        let source = BundleFile::from_bytes(Bytes::from(data.clone()));
+        let client = Client::builder().no_proxy().build().unwrap();
+        let token: Token =
+            serde_json::from_str(r#"{ "access_token": "test", "expires_in": 3600 }"#).unwrap();
+        let log = TestLogger;
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        let uploader = ArtifactUploader::new(
+            AndroidPublisherApi::new(&client, &token, &log),
+            &log,
+            ArtifactKind::Bundle {
+                ack_install_warning: false,
+            },
+        )
+        .chunk_size(CHUNK_GRANULARITY)
+        .progress(bytes_sent.clone());
+
+        tokio_test::block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let session_url = format!("http://{}/upload", listener.local_addr().unwrap());
+            let server = tokio::spawn(async move {
+                let mut received = Vec::new();
+                let mut content_ranges = Vec::new();
+
+                // The whole first chunk arrives, but the server only keeps half of it
+                let (mut stream, content_range, content_length) = accept(&listener).await;
+                let body = read_body(&mut stream, content_length).await;
+
+                content_ranges.push(content_range);
+                received.extend_from_slice(&body[..body.len() / 2]);
+                respond(
+                    &mut stream,
+                    "308 Resume Incomplete",
+                    &format!("Range: bytes=0-{}\r\n", received.len() - 1),
+                    "",
+                )
+                .await;
+
+                // The connection fails while the next chunk is sent
+                let (stream, content_range, _) = accept(&listener).await;
+
+                content_ranges.push(content_range);
+                drop(stream);
+
+                // The uploader asks how much was received
+                let (mut stream, content_range, _) = accept(&listener).await;
+
+                content_ranges.push(content_range);
+                respond(
+                    &mut stream,
+                    "308 Resume Incomplete",
+                    &format!("Range: bytes=0-{}\r\n", received.len() - 1),
+                    "",
+                )
+                .await;
+
+                // The rest of the bundle completes the upload
+                let (mut stream, content_range, content_length) = accept(&listener).await;
+
+                content_ranges.push(content_range);
+                received.extend(read_body(&mut stream, content_length).await);
+                respond(&mut stream, "200 OK", "", r#"{ "versionCode": 42 }"#).await;
+
+                (received, content_ranges)
+            });
+            let bundle: Bundle = uploader.send_chunks(&session_url, &source).await.unwrap();
+            let (received, content_ranges) = server.await.unwrap();
+
+            assert_eq!(bundle.version_code, 42);
+            assert_eq!(
+                content_ranges,
+                [
+                    "bytes 0-262143/393216",
+                    "bytes 131072-393215/393216",
+                    "bytes */393216",
+                    "bytes 131072-393215/393216",
+                ]
+            );
+            // Each byte that the server kept was sent exactly once, in order
+            assert_eq!(received, data);
+            assert_eq!(bytes_sent.load(Ordering::Relaxed), num_bytes);
+        });
+    }
+}
@@ -0,0 +1,87 @@
+use gplay::api_structs::*;
+use serde::de::DeserializeOwned;
+use std::path::PathBuf;
+
+fn load<T: DeserializeOwned>(name: &str) -> T {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+        .iter()
+        .collect();
+    let json = std::fs::read_to_string(&path).unwrap();
+
+    serde_json::from_str(&json).unwrap_or_else(|err| panic!("{}: {}", name, err))
+}
+
+#[test]
+fn edit_insert() {
+    let edit: EditInsert = load("edit_insert.json");
+
+    assert_eq!(edit.id, "01234567890123456789");
+    assert_eq!(edit.expiry_time_seconds.as_deref(), Some("1697581234"));
+}
+
+#[test]
+fn bundle() {
+    let bundle: Bundle = load("bundle.json");
+
+    assert_eq!(bundle.version_code, 10402);
+}
+
+#[test]
+fn bundles_list() {
+    let list: EditBundlesList = load("bundles_list.json");
+
+    assert_eq!(list.bundles.len(), 2);
+    assert_eq!(list.bundles[1].version_code, 10402);
+
+    let list: EditBundlesList = load("bundles_list_empty.json");
+
+    assert!(list.bundles.is_empty());
+}
+
+#[test]
+fn tracks_list() {
+    let list: TracksList = load("tracks_list.json");
+    let names: Vec<&str> = list.tracks.iter().map(|t| t.name.as_str()).collect();
+
+    assert_eq!(names, ["production", "beta", "alpha", "internal"]);
+    assert_eq!(list.tracks[0].releases[0].user_fraction, Some(0.2));
+    assert_eq!(
+        list.tracks[0].releases[0]
+            .release_notes
+            .as_ref()
+            .map(|notes| notes.len()),
+        Some(2)
+    );
+    assert_eq!(
+        list.tracks[1].releases[0]
+            .country_targeting
+            .as_ref()
+            .map(|targeting| targeting.countries.len()),
+        Some(2)
+    );
+    // Tracks without releases omit the field
+    assert!(list.tracks[2].releases.is_empty());
+}
+
+#[test]
+fn track() {
+    let track: Track = load("track.json");
+
+    assert_eq!(track.name, "internal");
+    assert_eq!(track.releases[0].status, "draft");
+}
+
+#[test]
+fn errors() {
+    let response: ErrorResponse = load("error_permission_denied.json");
+
+    assert_eq!(response.error.code, Some(403));
+    assert_eq!(response.error.status.as_deref(), Some("PERMISSION_DENIED"));
+
+    let response: ErrorResponse = load("error_version_code_used.json");
+
+    assert_eq!(
+        response.error.errors[0].reason.as_deref(),
+        Some("apkUpgradeVersionConflict")
+    );
+}